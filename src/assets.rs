@@ -0,0 +1,324 @@
+use std::{collections::HashMap, future::Future, marker::PhantomData, pin::Pin};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+use serde::{de::DeserializeOwned, Deserialize};
+use wasm_bindgen::JsValue;
+use web_sys::HtmlImageElement;
+
+use crate::{
+    browser::{self, FetchOptions},
+    engine::{self, Audio, Sheet, Sound},
+    error::{EngineError, Result},
+};
+
+#[derive(Deserialize, Default, Debug, PartialEq)]
+#[serde(default)]
+pub struct Manifest {
+    pub images: Vec<String>,
+    pub sheets: Vec<String>,
+    pub sounds: Vec<String>,
+    pub data: Vec<String>,
+    pub fetch: FetchOptions,
+    // Stands in for an asset that still fails after every retry.
+    pub fallbacks: HashMap<String, String>,
+}
+
+impl Manifest {
+    // Every path once, tagged with how to load it. Listing a path twice in
+    // the same section would otherwise fetch it twice.
+    fn entries(&self) -> Vec<(AssetKind, &str)> {
+        let mut entries: Vec<(AssetKind, &str)> = vec![];
+        for (kind, paths) in [
+            (AssetKind::Image, &self.images),
+            (AssetKind::Sheet, &self.sheets),
+            (AssetKind::Sound, &self.sounds),
+            (AssetKind::Data, &self.data),
+        ] {
+            for path in paths {
+                if !entries.contains(&(kind, path.as_str())) {
+                    entries.push((kind, path));
+                }
+            }
+        }
+        entries
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum AssetKind {
+    Image,
+    Sheet,
+    Sound,
+    Data,
+}
+
+enum Asset {
+    Image(HtmlImageElement),
+    Sheet(Sheet),
+    Sound(Sound),
+    Data(JsValue),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Progress {
+    pub loaded: usize,
+    pub total: usize,
+}
+
+impl Progress {
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.loaded as f32 / self.total as f32
+        }
+    }
+}
+
+// A path along with what kind of asset it names, so a sheet can't be looked
+// up as an image and data comes back as the type it was declared with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImageKey<'a>(pub &'a str);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SheetKey<'a>(pub &'a str);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SoundKey<'a>(pub &'a str);
+
+pub struct DataKey<T> {
+    path: &'static str,
+    data: PhantomData<fn() -> T>,
+}
+
+impl<T> DataKey<T> {
+    pub const fn new(path: &'static str) -> Self {
+        DataKey {
+            path,
+            data: PhantomData,
+        }
+    }
+}
+
+type AssetFuture<'a> = Pin<Box<dyn Future<Output = (String, Result<Asset>)> + 'a>>;
+
+#[derive(Default)]
+pub struct Assets {
+    images: HashMap<String, HtmlImageElement>,
+    sheets: HashMap<String, Sheet>,
+    sounds: HashMap<String, Sound>,
+    data: HashMap<String, JsValue>,
+}
+
+impl Assets {
+    // Loading again after a failure only requests what is still missing.
+    pub async fn load(
+        &mut self,
+        manifest_path: &str,
+        audio: &Audio,
+        on_progress: impl FnMut(Progress),
+    ) -> Result<()> {
+        let manifest: Manifest =
+            browser::fetch_json(manifest_path, FetchOptions::default()).await??;
+        self.load_manifest(&manifest, audio, on_progress).await
+    }
+
+    // Everything is requested at once and reported as each one lands, so
+    // the order of completion is whatever the network decides.
+    pub async fn load_manifest(
+        &mut self,
+        manifest: &Manifest,
+        audio: &Audio,
+        mut on_progress: impl FnMut(Progress),
+    ) -> Result<()> {
+        let mut progress = Progress {
+            loaded: 0,
+            total: manifest.entries().len(),
+        };
+        let options = manifest.fetch;
+        let mut pending: FuturesUnordered<AssetFuture> = self
+            .unloaded(manifest)
+            .into_iter()
+            .map(|(kind, path)| -> AssetFuture {
+                let fallback = manifest.fallbacks.get(path).map(String::as_str);
+                Box::pin(async move {
+                    let asset = match (load_asset(kind, path, audio, options).await, fallback) {
+                        (Err(err), Some(fallback)) => {
+                            error!("Could not load {}, using its fallback {:#?}", path, err);
+                            load_asset(kind, fallback, audio, options).await
+                        }
+                        (asset, _) => asset,
+                    };
+                    (path.to_string(), asset)
+                })
+            })
+            .collect();
+        progress.loaded = progress.total - pending.len();
+        on_progress(progress);
+
+        let mut failed: Vec<(String, EngineError)> = vec![];
+        while let Some((path, asset)) = pending.next().await {
+            match asset {
+                Ok(Asset::Image(image)) => {
+                    self.images.insert(path, image);
+                }
+                Ok(Asset::Sheet(sheet)) => {
+                    self.sheets.insert(path, sheet);
+                }
+                Ok(Asset::Sound(sound)) => {
+                    self.sounds.insert(path, sound);
+                }
+                Ok(Asset::Data(data)) => {
+                    self.data.insert(path, data);
+                }
+                Err(err) => {
+                    error!("Could not load {} {:#?}", path, err);
+                    failed.push((path, err));
+                }
+            }
+            progress.loaded += 1;
+            on_progress(progress);
+        }
+
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(EngineError::AssetLoad { failed })
+        }
+    }
+
+    fn unloaded<'a>(&self, manifest: &'a Manifest) -> Vec<(AssetKind, &'a str)> {
+        manifest
+            .entries()
+            .into_iter()
+            .filter(|(kind, path)| match kind {
+                AssetKind::Image => !self.images.contains_key(*path),
+                AssetKind::Sheet => !self.sheets.contains_key(*path),
+                AssetKind::Sound => !self.sounds.contains_key(*path),
+                AssetKind::Data => !self.data.contains_key(*path),
+            })
+            .collect()
+    }
+
+    pub fn image(&self, key: ImageKey) -> Result<HtmlImageElement> {
+        self.images
+            .get(key.0)
+            .cloned()
+            .ok_or_else(|| EngineError::missing_asset("image", key.0))
+    }
+
+    pub fn sheet(&self, key: SheetKey) -> Result<Sheet> {
+        self.sheets
+            .get(key.0)
+            .cloned()
+            .ok_or_else(|| EngineError::missing_asset("sprite sheet", key.0))
+    }
+
+    pub fn sound(&self, key: SoundKey) -> Result<Sound> {
+        self.sounds
+            .get(key.0)
+            .cloned()
+            .ok_or_else(|| EngineError::missing_asset("sound", key.0))
+    }
+
+    pub fn data<T: DeserializeOwned>(&self, key: DataKey<T>) -> Result<T> {
+        let value = self
+            .data
+            .get(key.path)
+            .cloned()
+            .ok_or_else(|| EngineError::missing_asset("data file", key.path))?;
+        serde_wasm_bindgen::from_value(value).map_err(|err| EngineError::json(key.path, err))
+    }
+}
+
+async fn load_asset(
+    kind: AssetKind,
+    path: &str,
+    audio: &Audio,
+    options: FetchOptions,
+) -> Result<Asset> {
+    match kind {
+        AssetKind::Image => engine::load_image(path, options).await.map(Asset::Image),
+        AssetKind::Sheet => browser::fetch_json(path, options).await?.map(Asset::Sheet),
+        AssetKind::Sound => audio.load_sound(path, options).await.map(Asset::Sound),
+        AssetKind::Data => browser::fetch_js_value(path, options)
+            .await
+            .map(Asset::Data),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_entries_skip_repeated_paths() {
+        let manifest: Manifest = serde_json::from_str(
+            r#"{"images": ["BG.png", "Stone.png", "BG.png"], "sounds": ["jump.mp3"]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            manifest.entries(),
+            vec![
+                (AssetKind::Image, "BG.png"),
+                (AssetKind::Image, "Stone.png"),
+                (AssetKind::Sound, "jump.mp3"),
+            ]
+        );
+    }
+
+    #[test]
+    fn loading_again_skips_what_already_loaded() {
+        let manifest: Manifest =
+            serde_json::from_str(r#"{"images": ["rhb.png"], "sheets": ["rhb.json"]}"#).unwrap();
+        let mut assets = Assets::default();
+        assets.sheets.insert(
+            "rhb.json".to_string(),
+            Sheet {
+                frames: HashMap::new(),
+                animations: HashMap::new(),
+            },
+        );
+
+        assert_eq!(
+            assets.unloaded(&manifest),
+            vec![(AssetKind::Image, "rhb.png")]
+        );
+    }
+
+    #[test]
+    fn manifest_configures_fetching_and_fallbacks() {
+        let manifest: Manifest = serde_json::from_str(
+            r#"{"fetch": {"retries": 5}, "fallbacks": {"song.ogg": "song.mp3"}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(manifest.fetch.retries, 5);
+        assert_eq!(
+            manifest.fetch.timeout_ms,
+            FetchOptions::default().timeout_ms
+        );
+        assert_eq!(manifest.fallbacks["song.ogg"], "song.mp3");
+    }
+
+    #[test]
+    fn an_empty_manifest_is_already_loaded() {
+        assert_eq!(
+            Progress {
+                loaded: 0,
+                total: 0
+            }
+            .fraction(),
+            1.0
+        );
+        assert_eq!(
+            Progress {
+                loaded: 1,
+                total: 4
+            }
+            .fraction(),
+            0.25
+        );
+    }
+}
@@ -1,6 +1,6 @@
-use anyhow::{anyhow, Result};
 use futures::Future;
-use js_sys::ArrayBuffer;
+use js_sys::{ArrayBuffer, Promise};
+use serde::Deserialize;
 use wasm_bindgen::{
     closure::{WasmClosure, WasmClosureFnOnce},
     prelude::Closure,
@@ -8,10 +8,12 @@ use wasm_bindgen::{
 };
 use wasm_bindgen_futures::JsFuture;
 use web_sys::{
-    CanvasRenderingContext2d, Document, Element, HtmlCanvasElement, HtmlElement, HtmlImageElement,
-    Response, Window,
+    AbortController, Blob, CanvasRenderingContext2d, Document, Element, HtmlCanvasElement,
+    HtmlElement, HtmlImageElement, HtmlInputElement, RequestInit, Response, Storage, Url, Window,
 };
 
+use crate::error::{EngineError, Result};
+
 macro_rules! log {
     ( $($t:tt)* ) => {
         web_sys::console::log_1(&format!( $($t)* ).into());
@@ -25,34 +27,34 @@ macro_rules! error {
 }
 
 pub fn window() -> Result<Window> {
-    web_sys::window().ok_or_else(|| anyhow!("No Window Found"))
+    web_sys::window().ok_or_else(|| EngineError::not_found("No Window Found"))
 }
 
 pub fn document() -> Result<Document> {
     window()?
         .document()
-        .ok_or_else(|| anyhow!("No Document Found"))
+        .ok_or_else(|| EngineError::not_found("No Document Found"))
 }
 
 pub fn canvas() -> Result<HtmlCanvasElement> {
     document()?
         .get_element_by_id("canvas")
-        .ok_or_else(|| anyhow!("No Canvas Found with ID 'canvas'"))?
+        .ok_or_else(|| EngineError::not_found("No Canvas Found with ID 'canvas'"))?
         .dyn_into::<HtmlCanvasElement>()
-        .map_err(|element| anyhow!("Error converting {:#?} to HtmlCanvasElement", element))
+        .map_err(|element| EngineError::dom("Error converting to HtmlCanvasElement", element))
 }
 
 pub fn context() -> Result<CanvasRenderingContext2d> {
     canvas()?
         .get_context("2d")
-        .map_err(|js_value| anyhow!("Error getting 2d context: {:#?}", js_value))?
-        .ok_or_else(|| anyhow!("No 2d context found"))?
+        .map_err(|js_value| EngineError::canvas("Error getting 2d context", js_value))?
+        .ok_or_else(|| EngineError::Canvas {
+            what: "No 2d context found".to_string(),
+            detail: None,
+        })?
         .dyn_into::<CanvasRenderingContext2d>()
         .map_err(|element| {
-            anyhow!(
-                "Error converting {:#?} to CanvasRenderingContext2d",
-                element
-            )
+            EngineError::canvas("Error converting to CanvasRenderingContext2d", element)
         })
 }
 
@@ -63,55 +65,160 @@ where
     wasm_bindgen_futures::spawn_local(future);
 }
 
-pub async fn fetch_with_str(resource: &str) -> Result<JsValue> {
-    JsFuture::from(window()?.fetch_with_str(resource))
-        .await
-        .map_err(|err| anyhow!("error fetching {:#?}", err))
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct FetchOptions {
+    pub timeout_ms: i32,
+    pub retries: u32,
+    pub backoff_ms: i32,
 }
 
-pub async fn fetch_response(resource: &str) -> Result<Response> {
-    fetch_with_str(resource)
-        .await?
-        .dyn_into()
-        .map_err(|err| anyhow!("error converting to Response {:#?}", err))
+impl Default for FetchOptions {
+    fn default() -> Self {
+        FetchOptions {
+            timeout_ms: 10_000,
+            retries: 2,
+            backoff_ms: 250,
+        }
+    }
+}
+
+impl FetchOptions {
+    // Doubles the wait after every failed attempt.
+    fn backoff(&self, attempt: u32) -> i32 {
+        self.backoff_ms.saturating_mul(1 << attempt.min(16))
+    }
 }
 
-pub async fn fetch_js_value(json_path: &str) -> Result<JsValue> {
-    let resp: Response = fetch_response(json_path).await?;
+// Runs `attempt` until it succeeds, fails permanently or runs out of retries.
+pub async fn with_retries<T, F, Fut>(
+    resource: &str,
+    options: FetchOptions,
+    mut attempt: F,
+) -> Result<T>
+where
+    F: FnMut(FetchOptions) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut tries = 0;
+    loop {
+        match attempt(options).await {
+            Ok(value) => return Ok(value),
+            Err(err) if err.is_transient() && tries < options.retries => {
+                log!("Retrying {} after {:#?}", resource, err);
+                sleep(options.backoff(tries)).await?;
+                tries += 1;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
 
-    JsFuture::from(
-        resp.json()
-            .map_err(|err| anyhow!("Could not get JSON from response {:#?}", err))?,
-    )
+// The abort timer stays armed until the body has been read, so a response
+// that stalls after its headers still times out and gets retried.
+async fn fetch_once(
+    resource: &str,
+    timeout_ms: i32,
+    read: fn(&Response) -> std::result::Result<Promise, JsValue>,
+) -> Result<JsValue> {
+    let controller = AbortController::new()
+        .map_err(|err| EngineError::dom("Could not create AbortController", err))?;
+    let abort_controller = controller.clone();
+    let on_timeout = closure_once(move || abort_controller.abort());
+    let timeout = set_timeout(&on_timeout, timeout_ms)?;
+
+    let init = RequestInit::new();
+    init.set_signal(Some(&controller.signal()));
+    let fetched = async {
+        let response: Response = JsFuture::from(window()?.fetch_with_str_and_init(resource, &init))
+            .await
+            .map_err(|err| EngineError::network(resource, err))?
+            .dyn_into()
+            .map_err(|err| EngineError::network(resource, err))?;
+        if !response.ok() {
+            return Err(EngineError::HttpStatus {
+                resource: resource.to_string(),
+                status: response.status(),
+                status_text: response.status_text(),
+            });
+        }
+        let body = read(&response).map_err(|err| EngineError::network(resource, err))?;
+        JsFuture::from(body)
+            .await
+            .map_err(|err| EngineError::network(resource, err))
+    }
+    .await;
+    clear_timeout(timeout);
+
+    match fetched {
+        // Nothing but the timer aborts this request.
+        Err(err) if err.exception_name().as_deref() == Some("AbortError") => {
+            Err(EngineError::Timeout {
+                resource: resource.to_string(),
+            })
+        }
+        fetched => fetched,
+    }
+}
+
+async fn fetch_body(
+    resource: &str,
+    options: FetchOptions,
+    read: fn(&Response) -> std::result::Result<Promise, JsValue>,
+) -> Result<JsValue> {
+    with_retries(resource, options, |options| {
+        fetch_once(resource, options.timeout_ms, read)
+    })
     .await
-    .map_err(|err| anyhow!("error fetching JSON{:#?}", err))
 }
 
-pub async fn fetch_json<T>(json_path: &str) -> Result<Result<T>>
+// Read as text and parsed here, so a malformed file isn't mistaken for a
+// network failure and retried.
+pub async fn fetch_js_value(json_path: &str, options: FetchOptions) -> Result<JsValue> {
+    let text = fetch_body(json_path, options, Response::text).await?;
+    let text = text
+        .as_string()
+        .ok_or_else(|| EngineError::json(json_path, text.clone()))?;
+    js_sys::JSON::parse(&text).map_err(|err| EngineError::json(json_path, err))
+}
+
+pub async fn fetch_json<T>(json_path: &str, options: FetchOptions) -> Result<Result<T>>
 where
     T: serde::de::DeserializeOwned,
 {
-    fetch_js_value(json_path).await.map(|json_value| {
-        serde_wasm_bindgen::from_value(json_value)
-            .map_err(|err| anyhow!("error deserializing JSON {:#?}", err))
+    fetch_js_value(json_path, options).await.map(|json_value| {
+        serde_wasm_bindgen::from_value(json_value).map_err(|err| EngineError::json(json_path, err))
     })
 }
 
-pub async fn fetch_array_buffer(resource: &str) -> Result<ArrayBuffer> {
-    let array_buffer = fetch_response(resource)
+pub async fn fetch_array_buffer(resource: &str, options: FetchOptions) -> Result<ArrayBuffer> {
+    fetch_body(resource, options, Response::array_buffer)
         .await?
-        .array_buffer()
-        .map_err(|err| anyhow!("Error loading array buffer {:#?}", err))?;
+        .dyn_into()
+        .map_err(|err| EngineError::network(resource, err))
+}
 
-    JsFuture::from(array_buffer)
-        .await
-        .map_err(|err| anyhow!("Error converting array buffer into a future {:#?}", err))?
+pub async fn fetch_blob(resource: &str, options: FetchOptions) -> Result<Blob> {
+    fetch_body(resource, options, Response::blob)
+        .await?
         .dyn_into()
-        .map_err(|err| anyhow!("Error converting raw JSValue to ArrayBuffer {:#?}", err))
+        .map_err(|err| EngineError::network(resource, err))
+}
+
+pub fn create_object_url(blob: &Blob) -> Result<String> {
+    Url::create_object_url_with_blob(blob)
+        .map_err(|err| EngineError::dom("Could not create an object URL", err))
+}
+
+pub fn revoke_object_url(url: &str) {
+    if let Err(err) = Url::revoke_object_url(url) {
+        error!("Could not revoke object URL {} {:#?}", url, err);
+    }
 }
 
 pub fn new_image() -> Result<HtmlImageElement> {
-    HtmlImageElement::new().map_err(|err| anyhow!("Could not create HtmlImageElement: {:#?}", err))
+    HtmlImageElement::new()
+        .map_err(|err| EngineError::dom("Could not create HtmlImageElement", err))
 }
 
 pub fn closure_once<F, A, R>(fn_once: F) -> Closure<F::FnMut>
@@ -130,24 +237,90 @@ pub type LoopClosure = Closure<dyn FnMut(f64)>;
 pub fn request_animation_frame(callback: &LoopClosure) -> Result<i32> {
     window()?
         .request_animation_frame(callback.as_ref().unchecked_ref())
-        .map_err(|err| anyhow!("Cannot request animation frame: {:#?}", err))
+        .map_err(|err| EngineError::dom("Cannot request animation frame", err))
+}
+
+pub fn cancel_animation_frame(handle: i32) {
+    if let Ok(window) = window() {
+        if let Err(err) = window.cancel_animation_frame(handle) {
+            error!("Could not cancel animation frame {:#?}", err);
+        }
+    }
 }
 
 pub fn create_raf_closure(f: impl FnMut(f64) + 'static) -> LoopClosure {
     closure_wrap(Box::new(f))
 }
 
+pub fn set_timeout(callback: &Closure<dyn FnMut()>, timeout_ms: i32) -> Result<i32> {
+    window()?
+        .set_timeout_with_callback_and_timeout_and_arguments_0(
+            callback.as_ref().unchecked_ref(),
+            timeout_ms,
+        )
+        .map_err(|err| EngineError::dom("Could not set timeout", err))
+}
+
+pub fn clear_timeout(handle: i32) {
+    if let Ok(window) = window() {
+        window.clear_timeout_with_handle(handle);
+    }
+}
+
+pub async fn sleep(duration_ms: i32) -> Result<()> {
+    let window = window()?;
+    let promise = Promise::new(&mut |resolve, _reject| {
+        if let Err(err) =
+            window.set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, duration_ms)
+        {
+            error!("Could not set timeout {:#?}", err);
+        }
+    });
+    JsFuture::from(promise)
+        .await
+        .map(|_value| ())
+        .map_err(|err| EngineError::dom("Error sleeping", err))
+}
+
 pub fn now() -> Result<f64> {
     Ok(window()?
         .performance()
-        .ok_or_else(|| anyhow!("Performance object not found"))?
+        .ok_or_else(|| EngineError::not_found("Performance object not found"))?
         .now())
 }
 
+pub fn local_storage() -> Result<Storage> {
+    window()?
+        .local_storage()
+        .map_err(|err| EngineError::dom("Could not access local storage", err))?
+        .ok_or_else(|| EngineError::not_found("No Local Storage Found"))
+}
+
+pub fn iso_date() -> String {
+    js_sys::Date::new_0().to_iso_string().into()
+}
+
+// For text that didn't come from us, such as anything read back from
+// local storage, before it goes into draw_ui.
+pub fn escape_html(text: &str) -> String {
+    text.chars()
+        .fold(String::with_capacity(text.len()), |mut escaped, c| {
+            match c {
+                '&' => escaped.push_str("&amp;"),
+                '<' => escaped.push_str("&lt;"),
+                '>' => escaped.push_str("&gt;"),
+                '"' => escaped.push_str("&quot;"),
+                '\'' => escaped.push_str("&#39;"),
+                c => escaped.push(c),
+            }
+            escaped
+        })
+}
+
 pub fn draw_ui(html: &str) -> Result<()> {
     find_ui().and_then(|ui| {
         ui.insert_adjacent_html("afterbegin", html)
-            .map_err(|err| anyhow!("Could not insert html {:#?}", err))
+            .map_err(|err| EngineError::dom("Could not insert html", err))
     })
 }
 
@@ -157,11 +330,11 @@ pub fn hide_ui() -> Result<()> {
     if let Some(child) = ui.first_child() {
         ui.remove_child(&child)
             .map(|_removed_child| ())
-            .map_err(|err| anyhow!("Failed to remove child {:#?}", err))
+            .map_err(|err| EngineError::dom("Failed to remove child", err))
             .and_then(|_unit| {
                 canvas()?
                     .focus()
-                    .map_err(|err| anyhow!("Could not set focus to canvas! {:#?}", err))
+                    .map_err(|err| EngineError::dom("Could not set focus to canvas!", err))
             })
     } else {
         Ok(())
@@ -171,7 +344,7 @@ pub fn hide_ui() -> Result<()> {
 fn find_ui() -> Result<Element> {
     document().and_then(|doc| {
         doc.get_element_by_id("ui")
-            .ok_or_else(|| anyhow!("UI element not found"))
+            .ok_or_else(|| EngineError::not_found("UI element not found"))
     })
 }
 
@@ -179,23 +352,52 @@ pub fn find_html_element_by_id(id: &str) -> Result<HtmlElement> {
     document()
         .and_then(|doc| {
             doc.get_element_by_id(id)
-                .ok_or_else(|| anyhow!("Element with id {} not found", id))
+                .ok_or_else(|| EngineError::not_found(format!("Element with id {} not found", id)))
         })
         .and_then(|element| {
             element
                 .dyn_into::<HtmlElement>()
-                .map_err(|element| anyhow!("Could not cast into HtmlElement {:#?}", element))
+                .map_err(|element| EngineError::dom("Could not cast into HtmlElement", element))
         })
 }
 
+pub fn add_document_listener(event: &str, listener: impl FnMut() + 'static) -> Result<()> {
+    let listener = closure_wrap(Box::new(listener) as Box<dyn FnMut()>);
+    document()?
+        .add_event_listener_with_callback(event, listener.as_ref().unchecked_ref())
+        .map_err(|err| EngineError::dom(format!("Could not listen for {}", event), err))?;
+    listener.forget();
+    Ok(())
+}
+
+pub fn find_input_element_by_id(id: &str) -> Result<HtmlInputElement> {
+    find_html_element_by_id(id).and_then(|element| {
+        element
+            .dyn_into::<HtmlInputElement>()
+            .map_err(|element| EngineError::dom("Could not cast into HtmlInputElement", element))
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use wasm_bindgen_test::wasm_bindgen_test;
     wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn backoff_doubles_with_each_attempt() {
+        let options = FetchOptions {
+            backoff_ms: 100,
+            ..FetchOptions::default()
+        };
+
+        assert_eq!(options.backoff(0), 100);
+        assert_eq!(options.backoff(3), 800);
+    }
+
     #[wasm_bindgen_test]
     async fn test_error_loading_json() {
-        let json = fetch_js_value("not_there.json").await;
+        let json = fetch_js_value("not_there.json", FetchOptions::default()).await;
         assert_eq!(json.is_err(), true);
     }
 }
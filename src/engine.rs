@@ -1,18 +1,32 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc, sync::Mutex};
+use std::{
+    cell::{self, RefCell},
+    collections::HashMap,
+    ops::{Add, Mul, Sub},
+    rc::Rc,
+    sync::Mutex,
+};
 
-use anyhow::{anyhow, Result};
 use async_trait::async_trait;
-use futures::channel::{
-    mpsc::{unbounded, UnboundedReceiver},
-    oneshot::channel,
+use futures::{
+    channel::{
+        mpsc::{unbounded, UnboundedReceiver},
+        oneshot::channel,
+    },
+    StreamExt,
 };
-use serde::Deserialize;
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
 use wasm_bindgen::{prelude::Closure, JsCast, JsValue};
-use web_sys::{AudioBuffer, AudioContext, CanvasRenderingContext2d, HtmlElement, HtmlImageElement};
+use web_sys::{
+    AudioBuffer, AudioBufferSourceNode, AudioContext, AudioContextState, CanvasRenderingContext2d,
+    GainNode, HtmlElement, HtmlImageElement,
+};
 
 use crate::{
-    browser::{self, LoopClosure},
+    browser::{self, FetchOptions, LoopClosure},
+    error::{EngineError, Result},
     sound,
+    synth::{self, SynthParams},
 };
 
 #[derive(Deserialize, Clone)]
@@ -23,19 +37,92 @@ pub struct SheetRect {
     pub h: i16,
 }
 
+#[derive(Deserialize, Clone)]
+pub struct SheetSize {
+    pub w: i16,
+    pub h: i16,
+}
+
 #[derive(Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Cell {
+    // Where the frame sits in the atlas, with its unrotated width and height.
     pub frame: SheetRect,
+    // Packed 90 degrees clockwise, so it occupies `frame.h` by `frame.w`.
+    #[serde(default)]
+    pub rotated: bool,
+    #[serde(default)]
+    pub trimmed: bool,
+    // The trimmed frame's placement within the original sprite.
     pub sprite_source_size: SheetRect,
+    pub source_size: Option<SheetSize>,
+    // Anchor as a fraction of the original sprite size.
+    #[serde(default)]
+    pub pivot: Point,
+}
+
+impl Cell {
+    // The region of the atlas image holding this frame.
+    pub fn source(&self) -> Rect {
+        let (width, height) = if self.rotated {
+            (self.frame.h, self.frame.w)
+        } else {
+            (self.frame.w, self.frame.h)
+        };
+        Rect::new_from_x_y(
+            self.frame.x.into(),
+            self.frame.y.into(),
+            width.into(),
+            height.into(),
+        )
+    }
+
+    // Where to draw this frame so that its pivot lands on `position`.
+    pub fn destination(&self, position: Point) -> Rect {
+        let (source_width, source_height) = self
+            .source_size
+            .as_ref()
+            .map_or((self.frame.w, self.frame.h), |size| (size.w, size.h));
+        let anchor = Point {
+            x: self.pivot.x * f32::from(source_width),
+            y: self.pivot.y * f32::from(source_height),
+        };
+        let trim_offset = if self.trimmed {
+            Point {
+                x: self.sprite_source_size.x.into(),
+                y: self.sprite_source_size.y.into(),
+            }
+        } else {
+            Point::default()
+        };
+
+        Rect::new(
+            position - anchor + trim_offset,
+            self.frame.w.into(),
+            self.frame.h.into(),
+        )
+    }
 }
 
 #[derive(Deserialize, Clone)]
 pub struct Sheet {
     pub frames: HashMap<String, Cell>,
+    // Frame names for each animation, in play order.
+    #[serde(default)]
+    pub animations: HashMap<String, Vec<String>>,
+}
+
+// The image is fetched first, so a missing file shows up as its status
+// rather than a bare error event, and only the fetch gets retried.
+pub async fn load_image(source: &str, options: FetchOptions) -> Result<HtmlImageElement> {
+    let blob = browser::fetch_blob(source, options).await?;
+    let url = browser::create_object_url(&blob)?;
+    let image = decode_image(source, &url).await;
+    browser::revoke_object_url(&url);
+    image
 }
 
-pub async fn load_image(source: &str) -> Result<HtmlImageElement> {
+async fn decode_image(source: &str, url: &str) -> Result<HtmlImageElement> {
     let image = browser::new_image()?;
 
     let (complete_tx, complete_rx) = channel::<Result<()>>();
@@ -50,9 +137,10 @@ pub async fn load_image(source: &str) -> Result<HtmlImageElement> {
         }
     });
 
+    let failed = source.to_string();
     let error_callback: Closure<dyn FnMut(JsValue)> = browser::closure_once(move |err| {
         if let Some(error_tx) = error_tx.lock().ok().and_then(|mut opt| opt.take()) {
-            if let Err(err) = error_tx.send(Err(anyhow!("Error Loading Image {:#?}", err))) {
+            if let Err(err) = error_tx.send(Err(EngineError::image_load(&failed, err))) {
                 error!("Error to send {:#?}", err);
             }
         }
@@ -60,38 +148,243 @@ pub async fn load_image(source: &str) -> Result<HtmlImageElement> {
 
     image.set_onload(Some(success_callback.as_ref().unchecked_ref()));
     image.set_onerror(Some(error_callback.as_ref().unchecked_ref()));
-    image.set_src(source);
+    image.set_src(url);
 
-    complete_rx.await??;
+    let loaded = complete_rx.await;
+    // The callbacks are dropped on return, so nothing may call them after.
+    image.set_onload(None);
+    image.set_onerror(None);
+    loaded.map_err(|_canceled| EngineError::ImageLoad {
+        source: source.to_string(),
+        detail: None,
+    })??;
 
     Ok(image)
 }
 
 #[async_trait(?Send)]
 pub trait Game {
-    async fn initialize(&mut self) -> Result<Box<dyn Game>>;
+    async fn initialize(&mut self, loading: &Loading) -> Result<Box<dyn Game>>;
     fn update(&mut self, keystate: &KeyState);
     fn draw(&self, renderer: &Renderer);
 }
 
-const FRAME_SIZE: f32 = 1.0 / 60.0 * 1000.0;
+pub const FRAME_SIZE: f32 = 1.0 / 60.0 * 1000.0;
 pub struct GameLoop {
     last_frame: f64,
     accumulated_delta: f32,
 }
 type SharedLoopClosure = Rc<RefCell<Option<LoopClosure>>>;
 
+// Lets a game report how far initialize has got while the loading scene
+// draws it.
+#[derive(Clone, Default)]
+pub struct Loading {
+    progress: Rc<cell::Cell<f32>>,
+}
+
+impl Loading {
+    pub fn set_progress(&self, fraction: f32) {
+        self.progress.set(fraction.clamp(0.0, 1.0));
+    }
+
+    pub fn progress(&self) -> f32 {
+        self.progress.get()
+    }
+}
+
+// Redraws every animation frame until dropped. Only used while the game
+// itself isn't running, for the loading and error screens.
+struct Scene {
+    frame: Rc<cell::Cell<i32>>,
+    closure: SharedLoopClosure,
+}
+
+impl Scene {
+    fn run(renderer: Renderer, draw: impl Fn(&Renderer) + 'static) -> Result<Self> {
+        let frame = Rc::new(cell::Cell::new(0));
+        let next_frame = frame.clone();
+        let f: SharedLoopClosure = Rc::new(RefCell::new(None));
+        let g = f.clone();
+
+        *g.borrow_mut() = Some(browser::create_raf_closure(move |_perf| {
+            renderer.clear(&renderer.bounds());
+            draw(&renderer);
+            match browser::request_animation_frame(f.borrow().as_ref().unwrap()) {
+                Ok(handle) => next_frame.set(handle),
+                Err(err) => error!("Error to request animation frame{:#?}", err),
+            }
+        }));
+
+        frame.set(browser::request_animation_frame(
+            g.borrow()
+                .as_ref()
+                .ok_or_else(|| EngineError::not_found("Scene: Loop is None"))?,
+        )?);
+        Ok(Scene { frame, closure: g })
+    }
+}
+
+impl Drop for Scene {
+    // The closure holds the cell it lives in, so it has to be taken out to be
+    // freed, and its pending frame cancelled so nothing calls it after.
+    fn drop(&mut self) {
+        browser::cancel_animation_frame(self.frame.get());
+        self.closure.borrow_mut().take();
+    }
+}
+
+const PROGRESS_BAR_WIDTH: f32 = 300.0;
+const PROGRESS_BAR_HEIGHT: f32 = 16.0;
+const ERROR_LINE_LENGTH: usize = 48;
+const ERROR_LINES: usize = 6;
+
+fn draw_loading_scene(renderer: &Renderer, progress: f32) {
+    let bounds = renderer.bounds();
+    let bar = Rect::new_from_x_y(
+        (bounds.width - PROGRESS_BAR_WIDTH) / 2.0,
+        (bounds.height - PROGRESS_BAR_HEIGHT) / 2.0,
+        PROGRESS_BAR_WIDTH,
+        PROGRESS_BAR_HEIGHT,
+    );
+    let filled = Rect::new_from_x_y(bar.x(), bar.y(), bar.width * progress, bar.height);
+
+    if let Err(err) = renderer.draw_text(
+        "Loading...",
+        &Point {
+            x: bar.x(),
+            y: bar.y() - 12.0,
+        },
+    ) {
+        error!("Could not draw loading text {:#?}", err);
+    }
+    renderer.fill_rect(&bar, "#dddddd");
+    renderer.fill_rect(&filled, "#d7263d");
+}
+
+fn draw_error_scene(renderer: &Renderer, headline: &str, lines: &[String]) {
+    let result = renderer
+        .draw_text(headline, &Point { x: 40.0, y: 200.0 })
+        .and_then(|_unit| {
+            lines.iter().enumerate().try_for_each(|(index, line)| {
+                renderer.draw_text(
+                    line,
+                    &Point {
+                        x: 40.0,
+                        y: 240.0 + index as f32 * 24.0,
+                    },
+                )
+            })
+        });
+    if let Err(err) = result {
+        error!("Could not draw error text {:#?}", err);
+    }
+}
+
+// Breaks on spaces where it can, and mid-word where it has to.
+fn wrap_text(text: &str, line_length: usize, max_lines: usize) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let mut word = word;
+        loop {
+            let separator = usize::from(!line.is_empty());
+            let space = line_length.saturating_sub(line.chars().count() + separator);
+            if word.chars().count() <= space {
+                if separator == 1 {
+                    line.push(' ');
+                }
+                line.push_str(word);
+                break;
+            }
+            if line.is_empty() {
+                let split = word
+                    .char_indices()
+                    .nth(line_length)
+                    .map_or(word.len(), |(index, _)| index);
+                lines.push(word[..split].to_string());
+                word = &word[split..];
+            } else {
+                lines.push(std::mem::take(&mut line));
+            }
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines.truncate(max_lines);
+    lines
+}
+
+// What the player can do about it depends on what broke.
+fn error_headline(err: &EngineError) -> &'static str {
+    match err {
+        // The first failure stands for the rest.
+        EngineError::AssetLoad { failed } => failed
+            .first()
+            .map_or("Something went wrong loading the game.", |(_path, err)| {
+                error_headline(err)
+            }),
+        EngineError::ImageLoad { .. } => "Some of the game's images would not load.",
+        err if err.is_transient() => "Could not reach the server. Check your connection.",
+        EngineError::HttpStatus { .. } | EngineError::MissingAsset { .. } => {
+            "Some of the game's files are missing."
+        }
+        EngineError::Json { .. } => "Some of the game's files are damaged.",
+        EngineError::AudioDecode { .. }
+            if err.exception_name().as_deref() == Some("EncodingError") =>
+        {
+            "This browser cannot play the format of the game's sounds."
+        }
+        EngineError::AudioDecode { .. } | EngineError::Audio { .. } => {
+            "This browser could not play the game's sounds."
+        }
+        EngineError::Dom { .. } | EngineError::Canvas { .. } => {
+            "This page is missing something the game needs."
+        }
+        _ => "Something went wrong loading the game.",
+    }
+}
+
+async fn wait_for_retry() -> Result<()> {
+    browser::draw_ui("<button id='retry'>Retry</button>")?;
+    let mut retry = add_click_handler(browser::find_html_element_by_id("retry")?);
+    retry.next().await;
+    browser::hide_ui()
+}
+
 impl GameLoop {
     pub async fn start(mut game: impl Game + 'static) -> Result<()> {
         let mut keyevent_receiver = prepare_input()?;
-        let mut game = game.initialize().await?;
+        let renderer = Renderer {
+            context: browser::context()?,
+        };
+        let mut game = loop {
+            let loading = Loading::default();
+            let progress = loading.clone();
+            let scene = Scene::run(renderer.clone(), move |renderer| {
+                draw_loading_scene(renderer, progress.progress())
+            })?;
+            let initialized = game.initialize(&loading).await;
+            drop(scene);
+
+            match initialized {
+                Ok(game) => break game,
+                Err(err) => {
+                    error!("Could not initialize the game {:#?}", err);
+                    let headline = error_headline(&err);
+                    let lines = wrap_text(&err.to_string(), ERROR_LINE_LENGTH, ERROR_LINES);
+                    let _scene = Scene::run(renderer.clone(), move |renderer| {
+                        draw_error_scene(renderer, headline, &lines)
+                    })?;
+                    wait_for_retry().await?;
+                }
+            }
+        };
         let mut game_loop = GameLoop {
             last_frame: browser::now()?,
             accumulated_delta: 0.0,
         };
-        let renderer = Renderer {
-            context: browser::context()?,
-        };
         let f: SharedLoopClosure = Rc::new(RefCell::new(None));
         let g = f.clone();
 
@@ -121,12 +414,13 @@ impl GameLoop {
         browser::request_animation_frame(
             g.borrow()
                 .as_ref()
-                .ok_or_else(|| anyhow!("GameLoop: Loop is None"))?,
+                .ok_or_else(|| EngineError::not_found("GameLoop: Loop is None"))?,
         )?;
         Ok(())
     }
 }
 
+#[derive(Clone)]
 pub struct Renderer {
     context: CanvasRenderingContext2d,
 }
@@ -141,7 +435,22 @@ impl Renderer {
         );
     }
 
-    pub fn draw_image(&self, image: &HtmlImageElement, frame: &Rect, destination: &Rect) {
+    // Positions are simulated with sub-pixel precision and only snapped to
+    // whole pixels here, so sprites don't blur between canvas pixels.
+    pub fn draw_image(
+        &self,
+        image: &HtmlImageElement,
+        frame: &Rect,
+        destination: &Rect,
+        options: &DrawOptions,
+    ) {
+        let destination = snap_to_pixels(destination);
+        // Untransformed draws skip saving and restoring the canvas state.
+        let transformed = *options != DrawOptions::default();
+        if transformed {
+            self.context.save();
+            self.apply_options(&destination, options);
+        }
         self.context
             .draw_image_with_html_image_element_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
                 image,
@@ -155,46 +464,327 @@ impl Renderer {
                 destination.height.into(),
             )
             .expect("Drawing is throwing exceptions!Unrecoverable error.");
+        if transformed {
+            self.context.restore();
+        }
+    }
+
+    // Draws an atlas region that was packed rotated 90 degrees clockwise,
+    // turning it back upright into `destination`.
+    pub fn draw_rotated_image(
+        &self,
+        image: &HtmlImageElement,
+        frame: &Rect,
+        destination: &Rect,
+        options: &DrawOptions,
+    ) {
+        let destination = snap_to_pixels(destination);
+        let center = destination.center();
+        self.context.save();
+        self.apply_options(&destination, options);
+        if let Err(err) = self
+            .context
+            .translate(center.x.into(), center.y.into())
+            .and_then(|_| self.context.rotate(-std::f64::consts::FRAC_PI_2))
+        {
+            error!("Error rotating frame {:#?}", err);
+        }
+        self.context
+            .draw_image_with_html_image_element_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
+                image,
+                frame.x().into(),
+                frame.y().into(),
+                frame.width.into(),
+                frame.height.into(),
+                (-destination.height / 2.0).into(),
+                (-destination.width / 2.0).into(),
+                destination.height.into(),
+                destination.width.into(),
+            )
+            .expect("Drawing is throwing exceptions!Unrecoverable error.");
+        self.context.restore();
+    }
+
+    // Must be called between save and restore.
+    fn apply_options(&self, destination: &Rect, options: &DrawOptions) {
+        if *options == DrawOptions::default() {
+            return;
+        }
+
+        let pivot = options.pivot_in(destination);
+        let scale = options.scale_factors();
+        self.context.set_global_alpha(options.alpha.into());
+        if let Err(err) = self
+            .context
+            .translate(pivot.x.into(), pivot.y.into())
+            .and_then(|_| self.context.rotate(options.rotation.into()))
+            .and_then(|_| self.context.scale(scale.x.into(), scale.y.into()))
+            .and_then(|_| self.context.translate((-pivot.x).into(), (-pivot.y).into()))
+        {
+            error!("Error applying draw options {:#?}", err);
+        }
     }
 
     pub fn draw_entire_image(&self, image: &HtmlImageElement, position: Point) {
         self.context
-            .draw_image_with_html_image_element(image, position.x.into(), position.y.into())
+            .draw_image_with_html_image_element(
+                image,
+                position.x.round().into(),
+                position.y.round().into(),
+            )
             .expect("Drawing is throwing exceptions!Unrecoverable error.");
     }
 
+    #[allow(dead_code)]
     pub fn draw_rect(&self, bounding_box: &Rect) {
+        self.draw_rects(std::slice::from_ref(bounding_box));
+    }
+
+    // Outlines every rect in a single path and stroke.
+    pub fn draw_rects(&self, bounding_boxes: &[Rect]) {
         self.context.set_stroke_style(&JsValue::from_str("#FF0000"));
         self.context.begin_path();
-        self.context.rect(
-            bounding_box.x().into(),
-            bounding_box.y().into(),
-            bounding_box.width.into(),
-            bounding_box.height.into(),
-        );
+        bounding_boxes.iter().for_each(|bounding_box| {
+            self.context.rect(
+                bounding_box.x().round().into(),
+                bounding_box.y().round().into(),
+                bounding_box.width.into(),
+                bounding_box.height.into(),
+            );
+        });
         self.context.stroke();
     }
 
+    pub fn width(&self) -> f32 {
+        self.context
+            .canvas()
+            .map(|canvas| canvas.width() as f32)
+            .unwrap_or_default()
+    }
+
+    pub fn bounds(&self) -> Rect {
+        let height = self
+            .context
+            .canvas()
+            .map(|canvas| canvas.height() as f32)
+            .unwrap_or_default();
+        Rect::new_from_x_y(0.0, 0.0, self.width(), height)
+    }
+
+    pub fn fill_rect(&self, rect: &Rect, color: &str) {
+        self.context.save();
+        self.context.set_fill_style_str(color);
+        self.context.fill_rect(
+            rect.x().into(),
+            rect.y().into(),
+            rect.width.into(),
+            rect.height.into(),
+        );
+        self.context.restore();
+    }
+
+    pub fn draw_with_camera(&self, camera: &Camera, draw: impl FnOnce(&Renderer)) {
+        let view = camera.view();
+        self.context.save();
+        if let Err(err) = self
+            .context
+            .translate((-view.x.round()).into(), (-view.y.round()).into())
+        {
+            error!("Error applying camera transform {:#?}", err);
+        }
+        draw(self);
+        self.context.restore();
+    }
+
     #[allow(dead_code)]
     pub fn draw_text(&self, text: &str, location: &Point) -> Result<()> {
         self.context.set_font("16pt serif");
         self.context
             .fill_text(text, location.x.into(), location.y.into())
-            .map_err(|err| anyhow!("Error filling text {:#?}", err))?;
-        Ok(())
+            .map_err(|err| EngineError::canvas("Error filling text", err))
     }
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+pub enum Layer {
+    #[default]
+    Background,
+    Obstacles,
+    Actors,
+    // In front of the actors, such as scenery they walk behind.
+    Foreground,
+    Debug,
+}
+
+#[derive(Clone)]
+enum DrawCommand {
+    Sprite {
+        image: HtmlImageElement,
+        source: Rect,
+        destination: Rect,
+        options: DrawOptions,
+        rotated: bool,
+    },
+    Image {
+        image: HtmlImageElement,
+        position: Point,
+    },
+    Outline(Rect),
+}
+
+// Draws submitted during a frame, played back in layer order by `flush`.
+// Submission order is kept within a layer.
 #[derive(Default)]
+pub struct RenderQueue {
+    commands: Vec<(Layer, DrawCommand)>,
+}
+
+impl RenderQueue {
+    pub fn draw_sprite(
+        &mut self,
+        layer: Layer,
+        image: &HtmlImageElement,
+        cell: &Cell,
+        destination: Rect,
+        options: DrawOptions,
+    ) {
+        self.commands.push((
+            layer,
+            DrawCommand::Sprite {
+                image: image.clone(),
+                source: cell.source(),
+                destination,
+                options,
+                rotated: cell.rotated,
+            },
+        ));
+    }
+
+    pub fn draw_entire_image(&mut self, layer: Layer, image: &HtmlImageElement, position: Point) {
+        self.commands.push((
+            layer,
+            DrawCommand::Image {
+                image: image.clone(),
+                position,
+            },
+        ));
+    }
+
+    pub fn draw_rect(&mut self, layer: Layer, bounding_box: &Rect) {
+        self.commands
+            .push((layer, DrawCommand::Outline(bounding_box.clone())));
+    }
+
+    fn sort(&mut self) {
+        self.commands.sort_by_key(|(layer, _)| *layer);
+    }
+
+    pub fn flush(&mut self, renderer: &Renderer) {
+        self.sort();
+
+        // Runs of outlines are batched into one stroke.
+        let mut outlines = vec![];
+        self.commands
+            .drain(..)
+            .for_each(|(_, command)| match command {
+                DrawCommand::Outline(bounding_box) => outlines.push(bounding_box),
+                command => {
+                    if !outlines.is_empty() {
+                        renderer.draw_rects(&outlines);
+                        outlines.clear();
+                    }
+                    match command {
+                        DrawCommand::Sprite {
+                            image,
+                            source,
+                            destination,
+                            options,
+                            rotated: true,
+                        } => renderer.draw_rotated_image(&image, &source, &destination, &options),
+                        DrawCommand::Sprite {
+                            image,
+                            source,
+                            destination,
+                            options,
+                            rotated: false,
+                        } => renderer.draw_image(&image, &source, &destination, &options),
+                        DrawCommand::Image { image, position } => {
+                            renderer.draw_entire_image(&image, position)
+                        }
+                        DrawCommand::Outline(_) => {}
+                    }
+                }
+            });
+        if !outlines.is_empty() {
+            renderer.draw_rects(&outlines);
+        }
+    }
+}
+
+fn snap_to_pixels(rect: &Rect) -> Rect {
+    Rect::new_from_x_y(rect.x().round(), rect.y().round(), rect.width, rect.height)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DrawOptions {
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+    // Radians, clockwise, about `pivot`.
+    pub rotation: f32,
+    // Fraction of the destination rect that rotation and scaling are about.
+    pub pivot: Point,
+    pub scale: Point,
+    pub alpha: f32,
+}
+
+impl Default for DrawOptions {
+    fn default() -> Self {
+        Self {
+            flip_horizontal: false,
+            flip_vertical: false,
+            rotation: 0.0,
+            pivot: Point { x: 0.5, y: 0.5 },
+            scale: Point { x: 1.0, y: 1.0 },
+            alpha: 1.0,
+        }
+    }
+}
+
+impl DrawOptions {
+    fn pivot_in(&self, destination: &Rect) -> Point {
+        destination.position
+            + Point {
+                x: destination.width * self.pivot.x,
+                y: destination.height * self.pivot.y,
+            }
+    }
+
+    // Flips are scales by -1 about the pivot.
+    fn scale_factors(&self) -> Point {
+        Point {
+            x: if self.flip_horizontal {
+                -self.scale.x
+            } else {
+                self.scale.x
+            },
+            y: if self.flip_vertical {
+                -self.scale.y
+            } else {
+                self.scale.y
+            },
+        }
+    }
+}
+
+#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Rect {
     pub position: Point,
-    pub width: i16,
-    pub height: i16,
+    pub width: f32,
+    pub height: f32,
 }
 
 impl Rect {
-    #[allow(dead_code)]
-    pub const fn new(position: Point, width: i16, height: i16) -> Self {
+    pub const fn new(position: Point, width: f32, height: f32) -> Self {
         Self {
             position,
             width,
@@ -202,7 +792,7 @@ impl Rect {
         }
     }
 
-    pub const fn new_from_x_y(x: i16, y: i16, width: i16, height: i16) -> Self {
+    pub const fn new_from_x_y(x: f32, y: f32, width: f32, height: f32) -> Self {
         Self {
             position: Point { x, y },
             width,
@@ -210,20 +800,21 @@ impl Rect {
         }
     }
 
-    pub fn x(&self) -> i16 {
+    pub fn x(&self) -> f32 {
         self.position.x
     }
 
-    pub fn set_x(&mut self, x: i16) {
+    #[allow(dead_code)]
+    pub fn set_x(&mut self, x: f32) {
         self.position.x = x;
     }
 
-    pub fn y(&self) -> i16 {
+    pub fn y(&self) -> f32 {
         self.position.y
     }
 
     #[allow(dead_code)]
-    pub fn set_y(&mut self, y: i16) {
+    pub fn set_y(&mut self, y: f32) {
         self.position.y = y;
     }
 
@@ -234,13 +825,230 @@ impl Rect {
             && self.bottom() > rect.y()
     }
 
-    pub fn right(&self) -> i16 {
+    pub fn right(&self) -> f32 {
         self.x() + self.width
     }
 
-    pub fn bottom(&self) -> i16 {
+    pub fn bottom(&self) -> f32 {
         self.y() + self.height
     }
+
+    pub fn center(&self) -> Point {
+        Point {
+            x: self.x() + self.width / 2.0,
+            y: self.y() + self.height / 2.0,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn contains(&self, point: Point) -> bool {
+        point.x >= self.x()
+            && point.x < self.right()
+            && point.y >= self.y()
+            && point.y < self.bottom()
+    }
+
+    pub fn translate(&self, offset: Point) -> Rect {
+        Rect::new(self.position + offset, self.width, self.height)
+    }
+
+    // Shrinks every side by `amount`; negative amounts grow the rect.
+    pub fn inset(&self, amount: Point) -> Rect {
+        Rect::new(
+            self.position + amount,
+            self.width - amount.x * 2.0,
+            self.height - amount.y * 2.0,
+        )
+    }
+
+    #[allow(dead_code)]
+    pub fn expand(&self, amount: Point) -> Rect {
+        self.inset(amount * -1.0)
+    }
+
+    pub fn intersection(&self, rect: &Rect) -> Option<Rect> {
+        self.intersects(rect).then(|| {
+            let x = self.x().max(rect.x());
+            let y = self.y().max(rect.y());
+            Rect::new_from_x_y(
+                x,
+                y,
+                self.right().min(rect.right()) - x,
+                self.bottom().min(rect.bottom()) - y,
+            )
+        })
+    }
+
+    pub fn union(&self, rect: &Rect) -> Rect {
+        let x = self.x().min(rect.x());
+        let y = self.y().min(rect.y());
+        Rect::new_from_x_y(
+            x,
+            y,
+            self.right().max(rect.right()) - x,
+            self.bottom().max(rect.bottom()) - y,
+        )
+    }
+
+    // How far the two rects overlap on each axis, zero if they don't intersect.
+    #[allow(dead_code)]
+    pub fn overlap_depth(&self, rect: &Rect) -> Point {
+        self.intersection(rect)
+            .map(|overlap| Point {
+                x: overlap.width,
+                y: overlap.height,
+            })
+            .unwrap_or_default()
+    }
+
+    // Moves this rect along `displacement` and reports the fraction of the
+    // move at which it first touches `target`, and the face it touched.
+    pub fn sweep(&self, displacement: Point, target: &Rect) -> Option<Sweep> {
+        let (entry_x, exit_x) = sweep_axis(
+            (self.x(), self.right()),
+            (target.x(), target.right()),
+            displacement.x,
+        )?;
+        let (entry_y, exit_y) = sweep_axis(
+            (self.y(), self.bottom()),
+            (target.y(), target.bottom()),
+            displacement.y,
+        )?;
+
+        let entry = entry_x.max(entry_y);
+        if entry < 0.0 || entry > 1.0 || entry >= exit_x.min(exit_y) {
+            return None;
+        }
+
+        let normal = if entry_y >= entry_x {
+            Point {
+                x: 0.0,
+                y: -displacement.y.signum(),
+            }
+        } else {
+            Point {
+                x: -displacement.x.signum(),
+                y: 0.0,
+            }
+        };
+        Some(Sweep {
+            time: entry,
+            normal,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sweep {
+    pub time: f32,
+    pub normal: Point,
+}
+
+// Times at which a moving span starts and stops overlapping a target span.
+fn sweep_axis(span: (f32, f32), target: (f32, f32), distance: f32) -> Option<(f32, f32)> {
+    if distance > 0.0 {
+        Some((
+            (target.0 - span.1) / distance,
+            (target.1 - span.0) / distance,
+        ))
+    } else if distance < 0.0 {
+        Some((
+            (target.1 - span.0) / distance,
+            (target.0 - span.1) / distance,
+        ))
+    } else if span.0 < target.1 && span.1 > target.0 {
+        Some((f32::NEG_INFINITY, f32::INFINITY))
+    } else {
+        None
+    }
+}
+
+// Sweep-and-prune along x: bounds are sorted by their left edge, so a query
+// binary searches to the first entry that could reach the area and stops at
+// the first one starting past it.
+#[derive(Default)]
+pub struct BroadPhase {
+    entries: Vec<(Rect, usize)>,
+    widest: f32,
+}
+
+impl BroadPhase {
+    pub fn new(bounds: impl IntoIterator<Item = Rect>) -> Self {
+        let mut entries: Vec<(Rect, usize)> = bounds
+            .into_iter()
+            .enumerate()
+            .map(|(index, bounds)| (bounds, index))
+            .collect();
+        entries.sort_by(|(first, _), (second, _)| first.x().total_cmp(&second.x()));
+        let widest = entries
+            .iter()
+            .map(|(bounds, _)| bounds.width)
+            .fold(0.0, f32::max);
+
+        Self { entries, widest }
+    }
+
+    // Indices of the bounds intersecting `area`, in insertion order.
+    pub fn query(&self, area: &Rect) -> Vec<usize> {
+        let start = self
+            .entries
+            .partition_point(|(bounds, _)| bounds.x() + self.widest <= area.x());
+        let mut indices: Vec<usize> = self.entries[start..]
+            .iter()
+            .take_while(|(bounds, _)| bounds.x() < area.right())
+            .filter(|(bounds, _)| bounds.intersects(area))
+            .map(|(_, index)| *index)
+            .collect();
+        indices.sort_unstable();
+        indices
+    }
+}
+
+#[derive(Default)]
+pub struct Camera {
+    position: Point,
+    shake_magnitude: f32,
+    shake_duration: u8,
+    shake_ticks: u8,
+}
+
+impl Camera {
+    pub fn x(&self) -> f32 {
+        self.position.x
+    }
+
+    // Keeps a target at `screen_x` pixels from the left edge of the view.
+    pub fn follow_horizontal(&mut self, target_x: f32, screen_x: f32) {
+        self.position.x = target_x - screen_x;
+    }
+
+    pub fn shake(&mut self, magnitude: f32, duration: u8) {
+        self.shake_magnitude = magnitude;
+        self.shake_duration = duration;
+        self.shake_ticks = duration;
+    }
+
+    pub fn update(&mut self) {
+        self.shake_ticks = self.shake_ticks.saturating_sub(1);
+    }
+
+    pub fn view(&self) -> Point {
+        if self.shake_ticks == 0 {
+            return self.position;
+        }
+
+        let direction = if self.shake_ticks.is_multiple_of(2) {
+            1.0
+        } else {
+            -1.0
+        };
+        let strength =
+            self.shake_magnitude * f32::from(self.shake_ticks) / f32::from(self.shake_duration);
+        Point {
+            x: self.position.x + direction * strength,
+            y: self.position.y - direction * strength,
+        }
+    }
 }
 
 enum KeyPress {
@@ -325,10 +1133,43 @@ impl KeyState {
     }
 }
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Point {
-    pub x: i16,
-    pub y: i16,
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Add for Point {
+    type Output = Point;
+
+    fn add(self, other: Point) -> Point {
+        Point {
+            x: self.x + other.x,
+            y: self.y + other.y,
+        }
+    }
+}
+
+impl Sub for Point {
+    type Output = Point;
+
+    fn sub(self, other: Point) -> Point {
+        Point {
+            x: self.x - other.x,
+            y: self.y - other.y,
+        }
+    }
+}
+
+impl Mul<f32> for Point {
+    type Output = Point;
+
+    fn mul(self, scale: f32) -> Point {
+        Point {
+            x: self.x * scale,
+            y: self.y * scale,
+        }
+    }
 }
 
 pub struct Image {
@@ -340,8 +1181,8 @@ impl Image {
     pub fn new(element: HtmlImageElement, position: Point) -> Self {
         let bounding_box = Rect {
             position,
-            width: element.width() as i16,
-            height: element.height() as i16,
+            width: element.width() as f32,
+            height: element.height() as f32,
         };
         Self {
             element,
@@ -349,81 +1190,1022 @@ impl Image {
         }
     }
 
-    pub fn draw(&self, renderer: &Renderer) {
-        renderer.draw_entire_image(&self.element, self.bounding_box.position);
-        renderer.draw_rect(&self.bounding_box);
+    pub fn draw(&self, queue: &mut RenderQueue, layer: Layer) {
+        queue.draw_entire_image(layer, &self.element, self.bounding_box.position);
+        queue.draw_rect(Layer::Debug, &self.bounding_box);
     }
 
     pub fn bounding_box(&self) -> &Rect {
         &self.bounding_box
     }
 
-    pub fn move_horizontal(&mut self, distance: i16) {
-        self.set_x(self.bounding_box.x() + distance);
-    }
-
-    pub fn set_x(&mut self, x: i16) {
-        self.bounding_box.set_x(x);
-    }
-
-    pub fn right(&self) -> i16 {
+    pub fn right(&self) -> f32 {
         self.bounding_box.right()
     }
 }
 
-pub struct SpriteSheet {
-    sheet: Sheet,
-    image: HtmlImageElement,
+#[derive(Deserialize)]
+pub struct ParallaxLayerConfig {
+    pub image: String,
+    pub scroll_factor: f32,
+    #[serde(default)]
+    pub offset_y: f32,
+    #[serde(default)]
+    pub layer: Layer,
+}
+
+pub struct ParallaxLayer {
+    element: HtmlImageElement,
+    scroll_factor: f32,
+    offset_y: f32,
+    layer: Layer,
+}
+
+impl ParallaxLayer {
+    pub fn new(element: HtmlImageElement, scroll_factor: f32, offset_y: f32, layer: Layer) -> Self {
+        Self {
+            element,
+            scroll_factor,
+            offset_y,
+            layer,
+        }
+    }
+
+    // Tiles the image across the view in screen space, then shifts the tiles
+    // by the camera, which the queue takes back off when it draws the world.
+    pub fn draw(&self, queue: &mut RenderQueue, camera: &Camera, view_width: f32) {
+        let width = self.element.width() as f32;
+        if width <= 0.0 {
+            return;
+        }
+
+        let view = camera.view();
+        let origin = Point {
+            x: view.x.round(),
+            y: view.y.round(),
+        };
+        let y = self.offset_y - view.y * self.scroll_factor;
+        let mut x = parallax_offset(view.x, self.scroll_factor, width);
+        while x < view_width {
+            queue.draw_entire_image(self.layer, &self.element, origin + Point { x, y });
+            x += width;
+        }
+    }
+}
+
+// Screen x of the leftmost visible tile of a layer `width` pixels wide.
+fn parallax_offset(view_x: f32, scroll_factor: f32, width: f32) -> f32 {
+    -(view_x * scroll_factor).rem_euclid(width)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameIndex(usize);
+
+// A sheet with its frame names resolved to indices once, so per-tick
+// lookups are a vector access instead of hashing a name.
+#[derive(Clone, Default)]
+pub struct Atlas {
+    cells: Vec<Cell>,
+    indices: HashMap<String, FrameIndex>,
+    animations: HashMap<String, Vec<FrameIndex>>,
+}
+
+impl From<Sheet> for Atlas {
+    fn from(sheet: Sheet) -> Self {
+        let mut frames: Vec<(String, Cell)> = sheet.frames.into_iter().collect();
+        frames.sort_by(|(first, _), (second, _)| first.cmp(second));
+        let indices: HashMap<String, FrameIndex> = frames
+            .iter()
+            .enumerate()
+            .map(|(index, (name, _))| (name.clone(), FrameIndex(index)))
+            .collect();
+        let animations = sheet
+            .animations
+            .into_iter()
+            .map(|(name, frame_names)| {
+                let frames = frame_names
+                    .iter()
+                    .filter_map(|frame_name| indices.get(frame_name).copied())
+                    .collect();
+                (name, frames)
+            })
+            .collect();
+
+        Self {
+            cells: frames.into_iter().map(|(_, cell)| cell).collect(),
+            indices,
+            animations,
+        }
+    }
+}
+
+impl Atlas {
+    pub fn index(&self, name: &str) -> Option<FrameIndex> {
+        self.indices.get(name).copied()
+    }
+
+    pub fn cell(&self, index: FrameIndex) -> Option<&Cell> {
+        self.cells.get(index.0)
+    }
+
+    // Uses the sheet's animation list for `name`, falling back to frames
+    // named like "Run (1).png", "Run (2).png"...
+    pub fn animation(&self, name: &str) -> Vec<FrameIndex> {
+        if let Some(frames) = self.animations.get(name) {
+            return frames.clone();
+        }
+
+        let mut numbered: Vec<(u32, FrameIndex)> = self
+            .indices
+            .iter()
+            .filter_map(|(frame_name, index)| {
+                frame_name
+                    .strip_prefix(name)?
+                    .strip_prefix(" (")?
+                    .strip_suffix(").png")?
+                    .parse()
+                    .ok()
+                    .map(|number| (number, *index))
+            })
+            .collect();
+        numbered.sort_by_key(|(number, _)| *number);
+        numbered.into_iter().map(|(_, index)| index).collect()
+    }
+}
+
+pub struct SpriteSheet {
+    atlas: Atlas,
+    image: HtmlImageElement,
 }
 
 impl SpriteSheet {
     pub fn new(sheet: Sheet, image: HtmlImageElement) -> Self {
-        Self { sheet, image }
+        Self {
+            atlas: sheet.into(),
+            image,
+        }
+    }
+
+    pub fn atlas(&self) -> &Atlas {
+        &self.atlas
     }
 
     pub fn cell(&self, name: &str) -> Option<&Cell> {
-        self.sheet.frames.get(name)
+        self.atlas
+            .index(name)
+            .and_then(|index| self.atlas.cell(index))
     }
 
-    pub fn draw(&self, renderer: &Renderer, source: &Rect, destination: &Rect) {
-        renderer.draw_image(&self.image, source, destination);
+    pub fn draw(
+        &self,
+        queue: &mut RenderQueue,
+        layer: Layer,
+        cell: &Cell,
+        destination: Rect,
+        options: DrawOptions,
+    ) {
+        queue.draw_sprite(layer, &self.image, cell, destination, options);
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoopMode {
+    Loop,
+    Once,
+    #[allow(dead_code)]
+    PingPong,
+}
+
+#[derive(Clone, Debug)]
+pub struct Clip {
+    frames: Vec<FrameIndex>,
+    // Milliseconds each frame stays on screen.
+    frame_duration: f32,
+    loop_mode: LoopMode,
+}
+
+impl Clip {
+    pub fn new(frames: Vec<FrameIndex>, frame_duration: f32, loop_mode: LoopMode) -> Self {
+        Self {
+            frames,
+            frame_duration,
+            loop_mode,
+        }
+    }
+
+    pub fn from_atlas(atlas: &Atlas, name: &str, frame_duration: f32, loop_mode: LoopMode) -> Self {
+        Self::new(atlas.animation(name), frame_duration, loop_mode)
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.frames.len() as f32 * self.frame_duration
+    }
+
+    pub fn frame_index(&self, elapsed: f32) -> usize {
+        let count = self.frames.len();
+        if count < 2 {
+            return 0;
+        }
+
+        let step = (elapsed.max(0.0) / self.frame_duration) as usize;
+        match self.loop_mode {
+            LoopMode::Loop => step % count,
+            LoopMode::Once => step.min(count - 1),
+            LoopMode::PingPong => {
+                let period = count * 2 - 2;
+                let step = step % period;
+                if step < count {
+                    step
+                } else {
+                    period - step
+                }
+            }
+        }
+    }
+
+    pub fn frame(&self, elapsed: f32) -> Option<FrameIndex> {
+        self.frames.get(self.frame_index(elapsed)).copied()
+    }
+
+    // Only clips that play once ever finish.
+    pub fn is_finished(&self, elapsed: f32) -> bool {
+        self.loop_mode == LoopMode::Once && elapsed >= self.duration()
+    }
+}
+
+// Identifies a clip within an `Animation`; owners pick their own ids.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClipId(pub usize);
+
+#[derive(Clone, Default)]
+pub struct Animation {
+    clips: Vec<Option<Clip>>,
+}
+
+impl Animation {
+    pub fn with_clip(mut self, id: ClipId, clip: Clip) -> Self {
+        if self.clips.len() <= id.0 {
+            self.clips.resize(id.0 + 1, None);
+        }
+        self.clips[id.0] = Some(clip);
+        self
+    }
+
+    pub fn clip(&self, id: ClipId) -> Option<&Clip> {
+        self.clips.get(id.0).and_then(Option::as_ref)
+    }
+
+    pub fn frame(&self, clip: ClipId, elapsed: f32) -> Option<FrameIndex> {
+        self.clip(clip).and_then(|clip| clip.frame(elapsed))
+    }
+
+    // A missing clip has nothing left to play.
+    pub fn is_finished(&self, clip: ClipId, elapsed: f32) -> bool {
+        self.clip(clip).is_none_or(|clip| clip.is_finished(elapsed))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Bus {
+    Master,
+    Music,
+    Effects,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MixerSettings {
+    pub master: f32,
+    pub music: f32,
+    pub effects: f32,
+    pub muted: bool,
+}
+
+impl Default for MixerSettings {
+    fn default() -> Self {
+        MixerSettings {
+            master: 1.0,
+            music: 1.0,
+            effects: 1.0,
+            muted: false,
+        }
+    }
+}
+
+impl MixerSettings {
+    pub fn volume(&self, bus: Bus) -> f32 {
+        match bus {
+            Bus::Master => self.master,
+            Bus::Music => self.music,
+            Bus::Effects => self.effects,
+        }
+    }
+
+    pub fn set_volume(&mut self, bus: Bus, volume: f32) {
+        let volume = volume.clamp(0.0, 1.0);
+        match bus {
+            Bus::Master => self.master = volume,
+            Bus::Music => self.music = volume,
+            Bus::Effects => self.effects = volume,
+        }
+    }
+
+    // Muting only silences the master bus so the bus levels survive an unmute.
+    pub fn gain(&self, bus: Bus) -> f32 {
+        match bus {
+            Bus::Master if self.muted => 0.0,
+            _ => self.volume(bus),
+        }
+    }
+}
+
+const MAX_QUEUED_SOUNDS: usize = 8;
+
 #[derive(Clone)]
 pub struct Audio {
     context: AudioContext,
+    master: GainNode,
+    music: GainNode,
+    effects: GainNode,
+    settings: Rc<RefCell<MixerSettings>>,
+    queued: Rc<RefCell<Vec<(SoundHandle, f64)>>>,
 }
 
 impl Audio {
     pub fn new() -> Result<Self> {
-        Ok(Self {
-            context: sound::create_audio_context()?,
-        })
+        let context = sound::create_audio_context()?;
+        let master = sound::create_gain(&context, 1.0)?;
+        sound::connect_with_audio_node(&master, &context.destination())?;
+        let music = sound::create_gain(&context, 1.0)?;
+        sound::connect_with_audio_node(&music, &master)?;
+        let effects = sound::create_gain(&context, 1.0)?;
+        sound::connect_with_audio_node(&effects, &master)?;
+
+        let audio = Self {
+            context,
+            master,
+            music,
+            effects,
+            settings: Rc::new(RefCell::new(MixerSettings::default())),
+            queued: Rc::new(RefCell::new(vec![])),
+        };
+        audio.unlock_on_interaction()?;
+        Ok(audio)
     }
 
-    pub async fn load_sound(&self, filename: &str) -> Result<Sound> {
-        let array_buffer = browser::fetch_array_buffer(filename).await?;
+    pub async fn load_sound(&self, filename: &str, options: FetchOptions) -> Result<Sound> {
+        let array_buffer = browser::fetch_array_buffer(filename, options).await?;
 
-        let audio_buffer = sound::decode_audio_data(&self.context, &array_buffer).await?;
+        let audio_buffer = sound::decode_audio_data(&self.context, filename, &array_buffer).await?;
 
         Ok(Sound {
             buffer: audio_buffer,
+            volume: 1.0,
+            variation: Variation::default(),
         })
     }
 
-    pub fn play_sound(&self, sound: &Sound) -> Result<()> {
-        sound::play_sound(&self.context, &sound.buffer, sound::Looping::No)
+    pub fn synthesize(&self, params: &SynthParams) -> Result<Sound> {
+        let sample_rate = self.context.sample_rate();
+        let samples = synth::synthesize(params, sample_rate);
+
+        Ok(Sound {
+            buffer: sound::create_buffer(&self.context, &samples, sample_rate)?,
+            volume: 1.0,
+            variation: Variation::default(),
+        })
+    }
+
+    pub fn settings(&self) -> MixerSettings {
+        *self.settings.borrow()
+    }
+
+    pub fn apply_settings(&self, settings: MixerSettings) {
+        *self.settings.borrow_mut() = settings;
+        self.update_gains();
+    }
+
+    pub fn set_volume(&self, bus: Bus, volume: f32) {
+        self.settings.borrow_mut().set_volume(bus, volume);
+        self.update_gains();
+    }
+
+    pub fn set_muted(&self, muted: bool) {
+        self.settings.borrow_mut().muted = muted;
+        self.update_gains();
+    }
+
+    fn update_gains(&self) {
+        let settings = self.settings.borrow();
+        self.master.gain().set_value(settings.gain(Bus::Master));
+        self.music.gain().set_value(settings.gain(Bus::Music));
+        self.effects.gain().set_value(settings.gain(Bus::Effects));
+    }
+
+    fn bus(&self, bus: Bus) -> &GainNode {
+        match bus {
+            Bus::Master => &self.master,
+            Bus::Music => &self.music,
+            Bus::Effects => &self.effects,
+        }
+    }
+
+    fn play_on(
+        &self,
+        bus: Bus,
+        sound: &Sound,
+        looping: sound::Looping,
+        pan: f32,
+        when: f64,
+    ) -> Result<SoundHandle> {
+        let (pitch, gain) = sound.variation.sample(&mut thread_rng());
+        let volume = sound.volume * gain;
+        let voice = sound::create_gain(&self.context, volume)?;
+        if pan == 0.0 {
+            sound::connect_with_audio_node(&voice, self.bus(bus))?;
+        } else {
+            let panner = sound::create_stereo_panner(&self.context, pan.clamp(-1.0, 1.0))?;
+            sound::connect_with_audio_node(&voice, &panner)?;
+            sound::connect_with_audio_node(&panner, self.bus(bus))?;
+        }
+        let mut playhead = Playhead::new(
+            sound.buffer.duration(),
+            matches!(looping, sound::Looping::Yes),
+        );
+        playhead.set_rate(0.0, pitch as f64);
+
+        let handle = SoundHandle {
+            context: self.context.clone(),
+            buffer: sound.buffer.clone(),
+            looping,
+            volume,
+            voice,
+            source: Rc::new(RefCell::new(None)),
+            playhead: Rc::new(RefCell::new(playhead)),
+            queued: Rc::new(cell::Cell::new(false)),
+        };
+        if self.is_running() {
+            handle.start(when)?;
+        } else if matches!(looping, sound::Looping::Yes) {
+            self.queue(&handle, when);
+        }
+        Ok(handle)
+    }
+
+    // Only looping sounds wait for the context, since an effect heard late
+    // is worse than one not heard. Stopped handles give up their place, and
+    // the oldest goes once the queue is full.
+    fn queue(&self, handle: &SoundHandle, when: f64) {
+        let mut queued = self.queued.borrow_mut();
+        queued.retain(|(handle, _)| handle.queued.get());
+        if queued.len() >= MAX_QUEUED_SOUNDS {
+            let (oldest, _) = queued.remove(0);
+            oldest.queued.set(false);
+        }
+        handle.queued.set(true);
+        queued.push((handle.clone(), when));
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.context.state() == AudioContextState::Running
+    }
+
+    // Browsers keep a context created before any user gesture suspended, so
+    // every interaction retries until it is running again.
+    fn unlock_on_interaction(&self) -> Result<()> {
+        for event in ["keydown", "click"] {
+            let audio = self.clone();
+            browser::add_document_listener(event, move || audio.resume())?;
+        }
+        Ok(())
+    }
+
+    fn resume(&self) {
+        if self.context.state() != AudioContextState::Suspended {
+            return;
+        }
+
+        let audio = self.clone();
+        browser::spawn_local(async move {
+            match sound::resume_context(&audio.context).await {
+                Ok(()) => audio.start_queued(),
+                Err(err) => error!("Could not resume audio {:#?}", err),
+            }
+        });
+    }
+
+    fn start_queued(&self) {
+        let queued: Vec<(SoundHandle, f64)> = self.queued.borrow_mut().drain(..).collect();
+        for (handle, when) in queued
+            .iter()
+            .filter(|(handle, _)| handle.queued.replace(false))
+        {
+            if let Err(err) = handle.start(*when) {
+                error!("Could not start queued sound {:#?}", err);
+            }
+        }
+    }
+
+    pub fn play_sound(&self, sound: &Sound) -> Result<SoundHandle> {
+        self.play_on(Bus::Effects, sound, sound::Looping::No, 0.0, 0.0)
+    }
+
+    // Pan runs from -1.0 (hard left) to 1.0 (hard right), see stereo_pan.
+    pub fn play_sound_panned(&self, sound: &Sound, pan: f32) -> Result<SoundHandle> {
+        self.play_on(Bus::Effects, sound, sound::Looping::No, pan, 0.0)
+    }
+
+    #[allow(dead_code)]
+    pub fn play_looping_sound(&self, sound: &Sound) -> Result<SoundHandle> {
+        self.play_on(Bus::Music, sound, sound::Looping::Yes, 0.0, 0.0)
+    }
+
+    // Starts at `when` on the context clock, which lets music land on a beat.
+    pub fn play_looping_sound_at(&self, sound: &Sound, when: f64) -> Result<SoundHandle> {
+        self.play_on(Bus::Music, sound, sound::Looping::Yes, 0.0, when)
     }
 
-    pub fn play_looping_sound(&self, sound: &Sound) -> Result<()> {
-        sound::play_sound(&self.context, &sound.buffer, sound::Looping::Yes)
+    pub fn current_time(&self) -> f64 {
+        self.context.current_time()
+    }
+}
+
+// Tracks where in the buffer playback is, in seconds, so a paused sound can
+// be restarted from the same spot on a fresh source node.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Playhead {
+    offset: f64,
+    started_at: Option<f64>,
+    rate: f64,
+    duration: f64,
+    loop_region: Option<(f64, f64)>,
+}
+
+impl Playhead {
+    fn new(duration: f64, looping: bool) -> Self {
+        Playhead {
+            offset: 0.0,
+            started_at: None,
+            rate: 1.0,
+            duration,
+            loop_region: looping.then_some((0.0, duration)),
+        }
+    }
+
+    fn position(&self, now: f64) -> f64 {
+        let position = match self.started_at {
+            Some(started_at) => self.offset + (now - started_at).max(0.0) * self.rate,
+            None => self.offset,
+        };
+
+        match self.loop_region {
+            Some((start, end)) if end > start && position >= end => {
+                start + (position - start) % (end - start)
+            }
+            Some(_) => position,
+            None => position.min(self.duration),
+        }
+    }
+
+    fn is_playing(&self) -> bool {
+        self.started_at.is_some()
+    }
+
+    fn play(&mut self, now: f64) {
+        self.started_at = Some(now);
+    }
+
+    fn pause(&mut self, now: f64) {
+        self.offset = self.position(now);
+        self.started_at = None;
+    }
+
+    fn rewind(&mut self) {
+        self.offset = 0.0;
+        self.started_at = None;
+    }
+
+    // Rebases onto the current position so earlier time keeps its old rate.
+    fn set_rate(&mut self, now: f64, rate: f64) {
+        if self.is_playing() {
+            self.offset = self.position(now);
+            self.started_at = Some(now);
+        }
+        self.rate = rate;
+    }
+
+    fn set_loop_region(&mut self, now: f64, start: f64, end: f64) {
+        if self.loop_region.is_some() {
+            self.offset = self.position(now);
+            self.started_at = self.started_at.map(|_| now);
+            self.loop_region = Some((start.max(0.0), end.min(self.duration)));
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SoundHandle {
+    context: AudioContext,
+    buffer: AudioBuffer,
+    looping: sound::Looping,
+    volume: f32,
+    voice: GainNode,
+    source: Rc<RefCell<Option<AudioBufferSourceNode>>>,
+    playhead: Rc<RefCell<Playhead>>,
+    queued: Rc<cell::Cell<bool>>,
+}
+
+impl SoundHandle {
+    fn start(&self, when: f64) -> Result<()> {
+        let when = when.max(self.context.current_time());
+        let playhead = *self.playhead.borrow();
+        let source =
+            sound::create_track_source(&self.context, &self.buffer, self.looping, &self.voice)?;
+        source.playback_rate().set_value(playhead.rate as f32);
+        if let Some((start, end)) = playhead.loop_region {
+            source.set_loop_start(start);
+            source.set_loop_end(end);
+        }
+        sound::start_sound(&source, when, playhead.position(when))?;
+
+        // A sound that plays to its end is rewound, as if it had been stopped.
+        let ended = {
+            let current = Rc::clone(&self.source);
+            let playhead = Rc::clone(&self.playhead);
+            let node = source.clone();
+            Closure::once_into_js(move || {
+                // Anything else means a stop or restart already replaced it.
+                if current.borrow().as_ref() == Some(&node) {
+                    current.replace(None);
+                    playhead.borrow_mut().rewind();
+                }
+            })
+        };
+        source
+            .add_event_listener_with_callback("ended", ended.unchecked_ref())
+            .map_err(|err| EngineError::audio("Could not listen for the end of a sound", err))?;
+
+        self.playhead.borrow_mut().play(when);
+        self.source.replace(Some(source));
+        Ok(())
+    }
+
+    fn stop_source(&self, when: f64) -> Result<()> {
+        match self.source.take() {
+            Some(source) => sound::stop_sound(&source, when),
+            None => Ok(()),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn stop(&self) -> Result<()> {
+        self.stop_at(0.0)
+    }
+
+    pub fn stop_at(&self, when: f64) -> Result<()> {
+        self.queued.set(false);
+        self.playhead.borrow_mut().rewind();
+        self.stop_source(when)
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    #[allow(dead_code)]
+    pub fn pause(&self) -> Result<()> {
+        self.queued.set(false);
+        if !self.is_paused() {
+            self.playhead
+                .borrow_mut()
+                .pause(self.context.current_time());
+            self.stop_source(0.0)?;
+        }
+        Ok(())
+    }
+
+    // Picks up where pause left off, or from the start after a stop.
+    #[allow(dead_code)]
+    pub fn resume(&self) -> Result<()> {
+        if self.is_paused() && !self.queued.get() {
+            self.start(0.0)?;
+        }
+        Ok(())
+    }
+
+    // Queued sounds count as paused until the context is unlocked.
+    pub fn is_paused(&self) -> bool {
+        !self.playhead.borrow().is_playing()
+    }
+
+    pub fn fade_to(&self, volume: f32, seconds: f64) -> Result<()> {
+        sound::ramp_to_value(&self.context, &self.voice.gain(), volume, seconds)
+    }
+
+    pub fn fade_to_at(&self, volume: f32, when: f64, seconds: f64) -> Result<()> {
+        sound::glide_to_value(&self.voice.gain(), volume, when, seconds)
+    }
+
+    pub fn fade_in_at(&self, volume: f32, when: f64, seconds: f64) -> Result<()> {
+        self.voice.gain().set_value(0.0);
+        self.fade_to_at(volume, when, seconds)
+    }
+
+    #[allow(dead_code)]
+    pub fn fade_in(&self, seconds: f64) -> Result<()> {
+        self.voice.gain().set_value(0.0);
+        self.fade_to(self.volume, seconds)
+    }
+
+    // Stops once silent, then restores the level so a later resume is audible.
+    pub fn fade_out(&self, seconds: f64) -> Result<()> {
+        self.queued.set(false);
+        let end = self.context.current_time() + seconds.max(0.0);
+        self.fade_to(0.0, seconds)?;
+        sound::set_value_at_time(&self.voice.gain(), self.volume, end)?;
+        self.playhead.borrow_mut().rewind();
+        self.stop_source(end)
+    }
+
+    #[allow(dead_code)]
+    pub fn set_playback_rate(&self, rate: f32) {
+        self.playhead
+            .borrow_mut()
+            .set_rate(self.context.current_time(), rate as f64);
+        if let Some(source) = self.source.borrow().as_ref() {
+            source.playback_rate().set_value(rate);
+        }
+    }
+
+    // Only looping sounds honour loop points.
+    #[allow(dead_code)]
+    pub fn set_loop_points(&self, start: f64, end: f64) {
+        self.playhead
+            .borrow_mut()
+            .set_loop_region(self.context.current_time(), start, end);
+        if let (Some(source), Some((start, end))) = (
+            self.source.borrow().as_ref(),
+            self.playhead.borrow().loop_region,
+        ) {
+            source.set_loop_start(start);
+            source.set_loop_end(end);
+        }
     }
 }
 
 #[derive(Clone)]
 pub struct Sound {
     pub buffer: AudioBuffer,
+    pub volume: f32,
+    pub variation: Variation,
+}
+
+// How far each play may stray from the recorded sound, as fractions. Pitch
+// swings both ways; volume only ever drops so a sound never clips louder.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Variation {
+    pub pitch: f32,
+    pub volume: f32,
+}
+
+impl Variation {
+    // Returns the (playback rate, gain) for a single play.
+    pub fn sample(&self, rng: &mut impl Rng) -> (f32, f32) {
+        let pitch = self.pitch.abs();
+        let volume = self.volume.clamp(0.0, 1.0);
+        (
+            1.0 + rng.gen_range(-pitch..=pitch),
+            1.0 - rng.gen_range(0.0..=volume),
+        )
+    }
+}
+
+// Maps a screen x coordinate onto the stereo field, centered on the view.
+pub fn stereo_pan(screen_x: f32, view_width: f32) -> f32 {
+    if view_width <= 0.0 {
+        return 0.0;
+    }
+    (screen_x / view_width * 2.0 - 1.0).clamp(-1.0, 1.0)
+}
+
+impl Sound {
+    pub fn with_volume(self, volume: f32) -> Self {
+        Sound {
+            volume: volume.max(0.0),
+            ..self
+        }
+    }
+
+    pub fn with_variation(self, pitch: f32, volume: f32) -> Self {
+        Sound {
+            variation: Variation { pitch, volume },
+            ..self
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub struct Tempo {
+    pub bpm: f64,
+    #[serde(default = "Tempo::default_beats_per_bar")]
+    pub beats_per_bar: u32,
+}
+
+impl Tempo {
+    fn default_beats_per_bar() -> u32 {
+        4
+    }
+
+    pub fn beat_length(&self) -> f64 {
+        60.0 / self.bpm.max(1.0)
+    }
+
+    pub fn bar_length(&self) -> f64 {
+        self.beat_length() * self.beats_per_bar.max(1) as f64
+    }
+
+    // The first beat at or after `now` for a track that started at `origin`.
+    pub fn next_beat(&self, origin: f64, now: f64) -> f64 {
+        next_boundary(origin, now, self.beat_length())
+    }
+
+    pub fn next_bar(&self, origin: f64, now: f64) -> f64 {
+        next_boundary(origin, now, self.bar_length())
+    }
+}
+
+fn next_boundary(origin: f64, now: f64, length: f64) -> f64 {
+    if now <= origin {
+        origin
+    } else {
+        origin + ((now - origin) / length).ceil() * length
+    }
+}
+
+#[derive(Deserialize)]
+pub struct StemConfig {
+    pub file: String,
+    #[serde(default)]
+    pub intensity: f32,
+    #[serde(default = "StemConfig::full_volume")]
+    pub volume: f32,
+}
+
+impl StemConfig {
+    fn full_volume() -> f32 {
+        1.0
+    }
+}
+
+#[derive(Deserialize)]
+pub struct MusicTrackConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub tempo: Tempo,
+    pub stems: Vec<StemConfig>,
+}
+
+// A stem joins the mix once the intensity reaches its threshold.
+struct Stem {
+    sound: Sound,
+    intensity: f32,
+}
+
+struct MusicTrack {
+    tempo: Tempo,
+    stems: Vec<Stem>,
+}
+
+struct PlayingTrack {
+    name: String,
+    tempo: Tempo,
+    origin: f64,
+    stems: Vec<(f32, SoundHandle)>,
+}
+
+pub struct Music {
+    audio: Audio,
+    tracks: HashMap<String, MusicTrack>,
+    playing: Option<PlayingTrack>,
+    intensity: f32,
+}
+
+impl Music {
+    pub fn new(audio: Audio) -> Self {
+        Music {
+            audio,
+            tracks: HashMap::new(),
+            playing: None,
+            intensity: 0.0,
+        }
+    }
+
+    // Stems are looked up rather than fetched, so they can come preloaded.
+    pub fn with_tracks(
+        audio: Audio,
+        configs: Vec<MusicTrackConfig>,
+        sound: impl Fn(&str) -> Result<Sound>,
+    ) -> Result<Self> {
+        let mut music = Music::new(audio);
+
+        for config in configs {
+            let mut stems = Vec::with_capacity(config.stems.len());
+            for stem in config.stems {
+                stems.push(Stem {
+                    sound: sound(&stem.file)?.with_volume(stem.volume),
+                    intensity: stem.intensity,
+                });
+            }
+            music.tracks.insert(
+                config.name,
+                MusicTrack {
+                    tempo: config.tempo,
+                    stems,
+                },
+            );
+        }
+
+        Ok(music)
+    }
+
+    pub fn current(&self) -> Option<&str> {
+        self.playing.as_ref().map(|playing| playing.name.as_str())
+    }
+
+    // Crossfades on the next bar of the current track.
+    pub fn play(&mut self, name: &str, crossfade: f64) -> Result<()> {
+        if self.current() == Some(name) {
+            return Ok(());
+        }
+        let track = self
+            .tracks
+            .get(name)
+            .ok_or_else(|| EngineError::missing_asset("music track", name))?;
+
+        let now = self.audio.current_time();
+        let when = self
+            .playing
+            .as_ref()
+            .map_or(now, |playing| playing.tempo.next_bar(playing.origin, now));
+
+        if let Some(playing) = self.playing.take() {
+            for (_, handle) in playing.stems {
+                handle.fade_to_at(0.0, when, crossfade)?;
+                handle.stop_at(when + crossfade)?;
+            }
+        }
+
+        let mut stems = Vec::with_capacity(track.stems.len());
+        for stem in &track.stems {
+            let handle = self.audio.play_looping_sound_at(&stem.sound, when)?;
+            let volume = if self.intensity >= stem.intensity {
+                handle.volume()
+            } else {
+                0.0
+            };
+            handle.fade_in_at(volume, when, crossfade)?;
+            stems.push((stem.intensity, handle));
+        }
+        self.playing = Some(PlayingTrack {
+            name: name.to_string(),
+            tempo: track.tempo,
+            origin: when,
+            stems,
+        });
+
+        Ok(())
+    }
+
+    // Fades out straight away rather than waiting for the bar.
+    pub fn stop(&mut self, fade: f64) -> Result<()> {
+        if let Some(playing) = self.playing.take() {
+            for (_, handle) in playing.stems {
+                handle.fade_out(fade)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Stems enter or drop out over a beat, starting on the next one.
+    pub fn set_intensity(&mut self, intensity: f32) -> Result<()> {
+        let intensity = intensity.clamp(0.0, 1.0);
+        let previous = std::mem::replace(&mut self.intensity, intensity);
+
+        if let Some(playing) = &self.playing {
+            let when = playing
+                .tempo
+                .next_beat(playing.origin, self.audio.current_time());
+            for (threshold, handle) in &playing.stems {
+                let audible = intensity >= *threshold;
+                if audible != (previous >= *threshold) {
+                    let volume = if audible { handle.volume() } else { 0.0 };
+                    handle.fade_to_at(volume, when, playing.tempo.beat_length())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
 }
 
 pub fn add_click_handler(elem: HtmlElement) -> UnboundedReceiver<()> {
@@ -438,23 +2220,547 @@ pub fn add_click_handler(elem: HtmlElement) -> UnboundedReceiver<()> {
     click_receiver
 }
 
+pub fn add_input_handler(elem: &HtmlElement, on_input: impl FnMut() + 'static) {
+    let on_input = browser::closure_wrap(Box::new(on_input) as Box<dyn FnMut()>);
+    elem.set_oninput(Some(on_input.as_ref().unchecked_ref()));
+    on_input.forget();
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
     #[test]
     fn two_rects_that_intersect_on_the_left() {
         let rect1 = Rect {
-            position: Point { x: 10, y: 10 },
-            height: 100,
-            width: 100,
+            position: Point { x: 10.0, y: 10.0 },
+            height: 100.0,
+            width: 100.0,
         };
         let rect2 = Rect {
-            position: Point { x: 0, y: 10 },
-            height: 100,
-            width: 100,
+            position: Point { x: 0.0, y: 10.0 },
+            height: 100.0,
+            width: 100.0,
         };
         assert_eq!(rect2.intersects(&rect1), true);
     }
+
+    #[test]
+    fn camera_shake_settles_back_on_the_followed_position() {
+        let mut camera = Camera::default();
+        camera.follow_horizontal(120.0, 20.0);
+        camera.shake(8.0, 2);
+
+        assert_ne!(camera.view(), Point { x: 100.0, y: 0.0 });
+        camera.update();
+        camera.update();
+        assert_eq!(camera.view(), Point { x: 100.0, y: 0.0 });
+    }
+
+    #[test]
+    fn playhead_survives_a_pause_and_a_rate_change() {
+        let mut playhead = Playhead::new(10.0, false);
+        playhead.play(1.0);
+        playhead.set_rate(3.0, 2.0);
+        assert_eq!(playhead.position(4.0), 4.0);
+
+        playhead.pause(4.0);
+        assert_eq!(playhead.position(100.0), 4.0);
+
+        playhead.play(100.0);
+        assert_eq!(playhead.position(104.0), 10.0);
+    }
+
+    #[test]
+    fn looping_playhead_wraps_inside_the_loop_points() {
+        let mut playhead = Playhead::new(10.0, true);
+        playhead.set_loop_region(0.0, 2.0, 6.0);
+        playhead.play(0.0);
+
+        assert_eq!(playhead.position(1.0), 1.0);
+        assert_eq!(playhead.position(7.0), 3.0);
+        assert_eq!(playhead.position(13.0), 5.0);
+    }
+
+    #[test]
+    fn a_playhead_that_ends_is_rewound() {
+        let mut playhead = Playhead::new(10.0, false);
+        playhead.play(0.0);
+        assert_eq!(playhead.position(100.0), 10.0);
+
+        playhead.rewind();
+        assert!(!playhead.is_playing());
+        assert_eq!(playhead.position(100.0), 0.0);
+    }
+
+    #[test]
+    fn sampled_variation_stays_within_its_range() {
+        let variation = Variation {
+            pitch: 0.1,
+            volume: 0.2,
+        };
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+
+        for _ in 0..100 {
+            let (pitch, gain) = variation.sample(&mut rng);
+            assert!((0.9..=1.1).contains(&pitch));
+            assert!((0.8..=1.0).contains(&gain));
+        }
+        assert_eq!(Variation::default().sample(&mut rng), (1.0, 1.0));
+    }
+
+    #[test]
+    fn stereo_pan_follows_the_screen_position() {
+        assert_eq!(stereo_pan(0.0, 600.0), -1.0);
+        assert_eq!(stereo_pan(300.0, 600.0), 0.0);
+        assert_eq!(stereo_pan(450.0, 600.0), 0.5);
+        assert_eq!(stereo_pan(900.0, 600.0), 1.0);
+    }
+
+    #[test]
+    fn tempo_finds_the_next_beat_and_bar() {
+        let tempo = Tempo {
+            bpm: 120.0,
+            beats_per_bar: 4,
+        };
+
+        assert_eq!(tempo.next_beat(10.0, 9.0), 10.0);
+        assert_eq!(tempo.next_beat(10.0, 10.2), 10.5);
+        assert_eq!(tempo.next_beat(10.0, 11.0), 11.0);
+        assert_eq!(tempo.next_bar(10.0, 10.2), 12.0);
+        assert_eq!(tempo.next_bar(10.0, 12.1), 14.0);
+    }
+
+    #[test]
+    fn music_track_config_fills_in_defaults() {
+        let config: MusicTrackConfig = serde_json::from_str(
+            r#"{"name": "walk", "bpm": 96.0, "stems": [{"file": "drums.mp3", "intensity": 0.5}]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.tempo.beats_per_bar, 4);
+        assert_eq!(config.stems[0].volume, 1.0);
+        assert_eq!(config.stems[0].intensity, 0.5);
+    }
+
+    #[test]
+    fn error_text_wraps_at_spaces_and_long_words() {
+        assert_eq!(
+            wrap_text("could not load rhb.png from the server", 16, 4),
+            vec!["could not load", "rhb.png from the", "server"]
+        );
+        assert_eq!(
+            wrap_text("JsValue(TypeError:NetworkError)", 12, 2),
+            vec!["JsValue(Type", "Error:Networ"]
+        );
+        assert!(wrap_text("", 12, 2).is_empty());
+    }
+
+    #[test]
+    fn error_headline_follows_what_broke() {
+        let missing = EngineError::HttpStatus {
+            resource: "rhb.png".to_string(),
+            status: 404,
+            status_text: "Not Found".to_string(),
+        };
+        let offline = EngineError::Network {
+            resource: "assets.json".to_string(),
+            detail: None,
+        };
+        let decode = EngineError::AudioDecode {
+            resource: "jump.mp3".to_string(),
+            detail: None,
+        };
+
+        assert_eq!(
+            error_headline(&missing),
+            "Some of the game's files are missing."
+        );
+        assert_eq!(
+            error_headline(&offline),
+            "Could not reach the server. Check your connection."
+        );
+        assert_eq!(
+            error_headline(&decode),
+            "This browser could not play the game's sounds."
+        );
+        assert_eq!(
+            error_headline(&EngineError::AssetLoad {
+                failed: vec![("assets.json".to_string(), offline)]
+            }),
+            "Could not reach the server. Check your connection."
+        );
+        assert_eq!(
+            error_headline(&EngineError::missing_asset("music track", "walk")),
+            "Some of the game's files are missing."
+        );
+        assert_eq!(
+            error_headline(&EngineError::Game {
+                what: "Error: Game is already initialized!".to_string()
+            }),
+            "Something went wrong loading the game."
+        );
+    }
+
+    #[test]
+    fn muting_silences_only_the_master_bus() {
+        let mut settings = MixerSettings::default();
+        settings.set_volume(Bus::Music, 1.5);
+        settings.set_volume(Bus::Effects, 0.4);
+        settings.muted = true;
+
+        assert_eq!(settings.gain(Bus::Master), 0.0);
+        assert_eq!(settings.gain(Bus::Music), 1.0);
+        assert_eq!(settings.gain(Bus::Effects), 0.4);
+        assert_eq!(settings.volume(Bus::Master), 1.0);
+    }
+
+    fn clip(loop_mode: LoopMode) -> Clip {
+        Clip::new(
+            vec![FrameIndex(0), FrameIndex(1), FrameIndex(2)],
+            50.0,
+            loop_mode,
+        )
+    }
+
+    #[test]
+    fn clips_step_through_frames_by_elapsed_time() {
+        let frames_at = |clip: &Clip| -> Vec<usize> {
+            (0..7)
+                .map(|step| clip.frame_index(step as f32 * 50.0 + 10.0))
+                .collect()
+        };
+
+        assert_eq!(frames_at(&clip(LoopMode::Loop)), [0, 1, 2, 0, 1, 2, 0]);
+        assert_eq!(frames_at(&clip(LoopMode::Once)), [0, 1, 2, 2, 2, 2, 2]);
+        assert_eq!(frames_at(&clip(LoopMode::PingPong)), [0, 1, 2, 1, 0, 1, 2]);
+    }
+
+    #[test]
+    fn only_clips_played_once_finish() {
+        assert!(!clip(LoopMode::Once).is_finished(149.0));
+        assert!(clip(LoopMode::Once).is_finished(150.0));
+        assert!(!clip(LoopMode::Loop).is_finished(1000.0));
+        assert!(!clip(LoopMode::PingPong).is_finished(1000.0));
+    }
+
+    #[test]
+    fn clips_fall_back_to_numbered_frame_names() {
+        let cell = Cell {
+            frame: SheetRect {
+                x: 0,
+                y: 0,
+                w: 1,
+                h: 1,
+            },
+            rotated: false,
+            trimmed: false,
+            sprite_source_size: SheetRect {
+                x: 0,
+                y: 0,
+                w: 1,
+                h: 1,
+            },
+            source_size: None,
+            pivot: Point::default(),
+        };
+        let sheet = Sheet {
+            frames: [
+                "Run (10).png",
+                "Run (2).png",
+                "Run (1).png",
+                "Runner (1).png",
+            ]
+            .into_iter()
+            .map(|name| (name.to_string(), cell.clone()))
+            .collect(),
+            animations: HashMap::new(),
+        };
+
+        let atlas = Atlas::from(sheet);
+        let clip = Clip::from_atlas(&atlas, "Run", 50.0, LoopMode::Loop);
+
+        assert_eq!(clip.frame(0.0), atlas.index("Run (1).png"));
+        assert_eq!(clip.frame(50.0), atlas.index("Run (2).png"));
+        assert_eq!(clip.frame(100.0), atlas.index("Run (10).png"));
+        assert_eq!(clip.duration(), 150.0);
+    }
+
+    #[test]
+    fn rotated_frames_occupy_a_transposed_atlas_region() {
+        let cell: Cell = serde_json::from_str(
+            r#"{
+                "frame": {"x":522,"y":1,"w":128,"h":99},
+                "rotated": true,
+                "trimmed": false,
+                "spriteSourceSize": {"x":0,"y":0,"w":128,"h":99},
+                "sourceSize": {"w":128,"h":99}
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(cell.source(), Rect::new_from_x_y(522.0, 1.0, 99.0, 128.0));
+        assert_eq!(
+            cell.destination(Point { x: 10.0, y: 20.0 }),
+            Rect::new_from_x_y(10.0, 20.0, 128.0, 99.0)
+        );
+    }
+
+    #[test]
+    fn trimmed_frames_are_placed_around_their_pivot() {
+        let cell: Cell = serde_json::from_str(
+            r#"{
+                "frame": {"x":0,"y":0,"w":71,"h":115},
+                "rotated": false,
+                "trimmed": true,
+                "spriteSourceSize": {"x":58,"y":8,"w":71,"h":115},
+                "sourceSize": {"w":160,"h":136},
+                "pivot": {"x":0.5,"y":1.0}
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(cell.source(), Rect::new_from_x_y(0.0, 0.0, 71.0, 115.0));
+        assert_eq!(
+            cell.destination(Point { x: 100.0, y: 200.0 }),
+            Rect::new_from_x_y(78.0, 72.0, 71.0, 115.0)
+        );
+    }
+
+    #[test]
+    fn render_queue_sorts_by_layer_keeping_submission_order() {
+        let mut queue = RenderQueue::default();
+        [
+            (Layer::Debug, 0.0),
+            (Layer::Actors, 1.0),
+            (Layer::Obstacles, 2.0),
+            (Layer::Actors, 3.0),
+            (Layer::Background, 4.0),
+            (Layer::Obstacles, 5.0),
+        ]
+        .into_iter()
+        .for_each(|(layer, x)| queue.draw_rect(layer, &Rect::new_from_x_y(x, 0.0, 1.0, 1.0)));
+
+        queue.sort();
+
+        let order: Vec<(Layer, f32)> = queue
+            .commands
+            .iter()
+            .map(|(layer, command)| match command {
+                DrawCommand::Outline(rect) => (*layer, rect.x()),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            order,
+            [
+                (Layer::Background, 4.0),
+                (Layer::Obstacles, 2.0),
+                (Layer::Obstacles, 5.0),
+                (Layer::Actors, 1.0),
+                (Layer::Actors, 3.0),
+                (Layer::Debug, 0.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn draw_options_flip_and_scale_about_their_pivot() {
+        let destination = Rect::new_from_x_y(100.0, 50.0, 40.0, 20.0);
+        let options = DrawOptions {
+            flip_horizontal: true,
+            scale: Point { x: 2.0, y: 0.5 },
+            pivot: Point { x: 0.5, y: 1.0 },
+            ..DrawOptions::default()
+        };
+
+        assert_eq!(options.scale_factors(), Point { x: -2.0, y: 0.5 });
+        assert_eq!(options.pivot_in(&destination), Point { x: 120.0, y: 70.0 });
+        assert_eq!(
+            DrawOptions::default().scale_factors(),
+            Point { x: 1.0, y: 1.0 }
+        );
+    }
+
+    #[test]
+    fn points_add_subtract_and_scale() {
+        let point = Point { x: 3.0, y: -2.0 };
+        let offset = Point { x: 1.5, y: 4.0 };
+
+        assert_eq!(point + offset, Point { x: 4.5, y: 2.0 });
+        assert_eq!(point - offset, Point { x: 1.5, y: -6.0 });
+        assert_eq!(point * 2.0, Point { x: 6.0, y: -4.0 });
+    }
+
+    #[test]
+    fn rects_intersect_into_the_shared_area() {
+        let first = Rect::new_from_x_y(0.0, 0.0, 20.0, 10.0);
+        let second = Rect::new_from_x_y(15.0, 5.0, 20.0, 20.0);
+
+        assert_eq!(
+            first.intersection(&second),
+            Some(Rect::new_from_x_y(15.0, 5.0, 5.0, 5.0))
+        );
+        assert_eq!(first.overlap_depth(&second), Point { x: 5.0, y: 5.0 });
+        assert_eq!(
+            first.union(&second),
+            Rect::new_from_x_y(0.0, 0.0, 35.0, 25.0)
+        );
+
+        let apart = Rect::new_from_x_y(40.0, 0.0, 5.0, 5.0);
+        assert_eq!(first.intersection(&apart), None);
+        assert_eq!(first.overlap_depth(&apart), Point::default());
+    }
+
+    #[test]
+    fn rects_contain_points_on_their_top_left_edges_only() {
+        let rect = Rect::new_from_x_y(10.0, 10.0, 10.0, 10.0);
+
+        assert!(rect.contains(Point { x: 10.0, y: 10.0 }));
+        assert!(rect.contains(rect.center()));
+        assert!(!rect.contains(Point { x: 20.0, y: 15.0 }));
+        assert!(!rect.contains(Point { x: 15.0, y: 20.0 }));
+    }
+
+    #[test]
+    fn rects_move_shrink_and_grow_around_their_center() {
+        let rect = Rect::new_from_x_y(10.0, 10.0, 40.0, 20.0);
+        let inset = rect.inset(Point { x: 5.0, y: 2.0 });
+
+        assert_eq!(inset, Rect::new_from_x_y(15.0, 12.0, 30.0, 16.0));
+        assert_eq!(inset.center(), rect.center());
+        assert_eq!(inset.expand(Point { x: 5.0, y: 2.0 }), rect);
+        assert_eq!(
+            rect.translate(Point { x: -10.0, y: 5.0 }),
+            Rect::new_from_x_y(0.0, 15.0, 40.0, 20.0)
+        );
+    }
+
+    #[test]
+    fn a_fast_fall_hits_the_top_of_a_thin_platform() {
+        let boy = Rect::new_from_x_y(100.0, 300.0, 40.0, 10.0);
+        let platform = Rect::new_from_x_y(80.0, 320.0, 200.0, 4.0);
+        let falling = Point { x: 4.0, y: 40.0 };
+
+        let boy_after_fall = Rect::new_from_x_y(104.0, 340.0, 40.0, 10.0);
+        assert!(!boy_after_fall.intersects(&platform));
+        assert_eq!(
+            boy.sweep(falling, &platform),
+            Some(Sweep {
+                time: 0.25,
+                normal: Point { x: 0.0, y: -1.0 }
+            })
+        );
+    }
+
+    #[test]
+    fn sweeps_report_side_hits_and_misses() {
+        let boy = Rect::new_from_x_y(0.0, 0.0, 10.0, 10.0);
+        let wall = Rect::new_from_x_y(15.0, -20.0, 5.0, 40.0);
+
+        let hit = boy.sweep(Point { x: 10.0, y: 0.0 }, &wall).unwrap();
+        assert_eq!(hit.time, 0.5);
+        assert_eq!(hit.normal, Point { x: -1.0, y: 0.0 });
+        assert_eq!(boy.sweep(Point { x: 4.0, y: 0.0 }, &wall), None);
+        assert_eq!(boy.sweep(Point { x: 0.0, y: 30.0 }, &wall), None);
+    }
+
+    fn obstacle_course(count: usize) -> Vec<Rect> {
+        (0..count)
+            .map(|index| {
+                let x = index as f32 * 150.0;
+                let height = 50.0 + (index % 4) as f32 * 40.0;
+                Rect::new_from_x_y(x, 600.0 - height, 60.0 + (index % 3) as f32 * 80.0, height)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn broad_phase_matches_a_linear_scan() {
+        let course = obstacle_course(200);
+        let broad_phase = BroadPhase::new(course.clone());
+
+        (0..400).for_each(|step| {
+            let area = Rect::new_from_x_y(step as f32 * 75.0 - 30.0, 420.0, 70.0, 140.0);
+            let expected: Vec<usize> = course
+                .iter()
+                .enumerate()
+                .filter(|(_, bounds)| bounds.intersects(&area))
+                .map(|(index, _)| index)
+                .collect();
+            assert_eq!(broad_phase.query(&area), expected);
+        });
+    }
+
+    // cargo test --release broad_phase_benchmark -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn broad_phase_benchmark() {
+        const QUERIES: usize = 10_000;
+
+        [100, 400, 1600, 6400].into_iter().for_each(|count| {
+            let course = obstacle_course(count);
+            let broad_phase = BroadPhase::new(course.clone());
+            let areas: Vec<Rect> = (0..QUERIES)
+                .map(|step| {
+                    let x = (step * 37 % count) as f32 * 150.0;
+                    Rect::new_from_x_y(x, 420.0, 70.0, 140.0)
+                })
+                .collect();
+
+            let start = std::time::Instant::now();
+            let linear_hits: usize = areas
+                .iter()
+                .map(|area| {
+                    course
+                        .iter()
+                        .filter(|bounds| bounds.intersects(area))
+                        .count()
+                })
+                .sum();
+            let linear = start.elapsed();
+
+            let start = std::time::Instant::now();
+            let broad_phase_hits: usize =
+                areas.iter().map(|area| broad_phase.query(area).len()).sum();
+            let swept = start.elapsed();
+
+            assert_eq!(linear_hits, broad_phase_hits);
+            println!(
+                "{:>5} obstacles: linear {:>10.2?} broad phase {:>10.2?} ({} queries)",
+                count, linear, swept, QUERIES
+            );
+        });
+    }
+
+    #[test]
+    fn parallax_layers_wrap_at_their_own_rate() {
+        assert_eq!(parallax_offset(0.0, 0.5, 600.0), 0.0);
+        assert_eq!(parallax_offset(700.0, 1.0, 600.0), -100.0);
+        assert_eq!(parallax_offset(700.0, 0.5, 600.0), -350.0);
+        assert_eq!(parallax_offset(-100.0, 1.0, 600.0), -500.0);
+    }
+
+    // Only a browser can make the exceptions these errors keep.
+    #[cfg(target_arch = "wasm32")]
+    mod in_browser {
+        use super::*;
+        use wasm_bindgen_test::wasm_bindgen_test;
+
+        #[wasm_bindgen_test]
+        fn error_headline_reads_the_exception_name() {
+            let unsupported = js_sys::Error::new("Unable to decode audio data");
+            unsupported.set_name("EncodingError");
+            let decode = EngineError::audio_decode("jump.mp3", unsupported);
+
+            assert_eq!(decode.exception_name().as_deref(), Some("EncodingError"));
+            assert_eq!(
+                error_headline(&decode),
+                "This browser cannot play the format of the game's sounds."
+            );
+        }
+    }
 }
 
 unsafe fn draw_frame_rate(render: &Renderer, frame_time: f64) {
@@ -473,7 +2779,7 @@ unsafe fn draw_frame_rate(render: &Renderer, frame_time: f64) {
 
     if let Err(err) = render.draw_text(
         &format!("Frame Rate: {}", FRAME_RATE),
-        &Point { x: 400, y: 100 },
+        &Point { x: 400.0, y: 100.0 },
     ) {
         error!("Could not draw text {:#?}", err);
     }
@@ -0,0 +1,285 @@
+use std::fmt::{self, Display};
+
+use js_sys::Reflect;
+use wasm_bindgen::JsValue;
+
+// Keeps whatever the browser threw as it was, so it can still be inspected
+// or logged whole. That makes it neither Send nor Sync, so engine code
+// returns it directly instead of through anyhow.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EngineError {
+    // A window, document or element that is missing or isn't what we expected.
+    Dom {
+        what: String,
+        detail: Option<JsValue>,
+    },
+    // The request never got a response.
+    Network {
+        resource: String,
+        detail: Option<JsValue>,
+    },
+    Timeout {
+        resource: String,
+    },
+    HttpStatus {
+        resource: String,
+        status: u16,
+        status_text: String,
+    },
+    Json {
+        resource: String,
+        detail: Option<JsValue>,
+    },
+    ImageLoad {
+        source: String,
+        detail: Option<JsValue>,
+    },
+    AudioDecode {
+        resource: String,
+        detail: Option<JsValue>,
+    },
+    // Creating, connecting or scheduling Web Audio nodes.
+    Audio {
+        what: String,
+        detail: Option<JsValue>,
+    },
+    Canvas {
+        what: String,
+        detail: Option<JsValue>,
+    },
+    // Asked for by name, but never loaded or declared.
+    MissingAsset {
+        kind: &'static str,
+        name: String,
+    },
+    // Every asset that failed, in the order they did.
+    AssetLoad {
+        failed: Vec<(String, EngineError)>,
+    },
+    // Anything the game reports through anyhow, in its own words.
+    Game {
+        what: String,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, EngineError>;
+
+impl EngineError {
+    pub fn dom(what: impl Into<String>, err: impl Into<JsValue>) -> Self {
+        EngineError::Dom {
+            what: what.into(),
+            detail: Some(err.into()),
+        }
+    }
+
+    pub fn not_found(what: impl Into<String>) -> Self {
+        EngineError::Dom {
+            what: what.into(),
+            detail: None,
+        }
+    }
+
+    pub fn network(resource: &str, err: impl Into<JsValue>) -> Self {
+        EngineError::Network {
+            resource: resource.to_string(),
+            detail: Some(err.into()),
+        }
+    }
+
+    pub fn json(resource: &str, err: impl Into<JsValue>) -> Self {
+        EngineError::Json {
+            resource: resource.to_string(),
+            detail: Some(err.into()),
+        }
+    }
+
+    pub fn image_load(source: &str, err: impl Into<JsValue>) -> Self {
+        EngineError::ImageLoad {
+            source: source.to_string(),
+            detail: Some(err.into()),
+        }
+    }
+
+    pub fn audio_decode(resource: &str, err: impl Into<JsValue>) -> Self {
+        EngineError::AudioDecode {
+            resource: resource.to_string(),
+            detail: Some(err.into()),
+        }
+    }
+
+    pub fn audio(what: impl Into<String>, err: impl Into<JsValue>) -> Self {
+        EngineError::Audio {
+            what: what.into(),
+            detail: Some(err.into()),
+        }
+    }
+
+    pub fn canvas(what: impl Into<String>, err: impl Into<JsValue>) -> Self {
+        EngineError::Canvas {
+            what: what.into(),
+            detail: Some(err.into()),
+        }
+    }
+
+    pub fn missing_asset(kind: &'static str, name: impl Into<String>) -> Self {
+        EngineError::MissingAsset {
+            kind,
+            name: name.into(),
+        }
+    }
+
+    pub fn detail(&self) -> Option<&JsValue> {
+        match self {
+            EngineError::Dom { detail, .. }
+            | EngineError::Network { detail, .. }
+            | EngineError::Json { detail, .. }
+            | EngineError::ImageLoad { detail, .. }
+            | EngineError::AudioDecode { detail, .. }
+            | EngineError::Audio { detail, .. }
+            | EngineError::Canvas { detail, .. } => detail.as_ref(),
+            _ => None,
+        }
+    }
+
+    // Errors and DOMExceptions name what went wrong, such as "AbortError",
+    // more reliably than their messages do.
+    pub fn exception_name(&self) -> Option<String> {
+        self.detail()
+            .and_then(|detail| Reflect::get(detail, &JsValue::from_str("name")).ok())
+            .and_then(|name| name.as_string())
+    }
+
+    // Whether the same request might succeed if it's made again. An image
+    // that fails to decode has already been fetched, so it would fail again.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            EngineError::Network { .. } | EngineError::Timeout { .. } => true,
+            EngineError::HttpStatus { status, .. } => is_transient_status(*status),
+            _ => false,
+        }
+    }
+}
+
+// Client errors won't change on a retry, apart from timeouts and throttling.
+fn is_transient_status(status: u16) -> bool {
+    status >= 500 || status == 408 || status == 429
+}
+
+impl Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineError::Dom { what, .. } | EngineError::Canvas { what, .. } => {
+                write!(f, "{}", what)
+            }
+            EngineError::Network { resource, .. } => write!(f, "Could not fetch {}", resource),
+            EngineError::Timeout { resource } => write!(f, "Timed out loading {}", resource),
+            EngineError::HttpStatus {
+                resource,
+                status,
+                status_text,
+            } => write!(f, "{} responded with {} {}", resource, status, status_text),
+            EngineError::Json { resource, .. } => {
+                write!(f, "{} is not the JSON we expected", resource)
+            }
+            EngineError::ImageLoad { source, .. } => write!(f, "Could not load image {}", source),
+            EngineError::AudioDecode { resource, .. } => {
+                write!(f, "Could not decode audio {}", resource)
+            }
+            EngineError::Audio { what, .. } => write!(f, "{}", what),
+            EngineError::MissingAsset { kind, name } => write!(f, "No {} named {}", kind, name),
+            EngineError::AssetLoad { failed } => {
+                let paths = failed
+                    .iter()
+                    .map(|(path, _err)| path.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "Could not load {}", paths)
+            }
+            EngineError::Game { what } => write!(f, "{}", what),
+        }
+    }
+}
+
+impl std::error::Error for EngineError {}
+
+impl From<anyhow::Error> for EngineError {
+    fn from(err: anyhow::Error) -> Self {
+        EngineError::Game {
+            what: format!("{:#}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_server_trouble_is_worth_retrying() {
+        assert!(is_transient_status(503));
+        assert!(is_transient_status(429));
+        assert!(!is_transient_status(404));
+        assert!(!is_transient_status(403));
+    }
+
+    #[test]
+    fn retries_depend_on_what_went_wrong() {
+        let status = |status| EngineError::HttpStatus {
+            resource: "assets.json".to_string(),
+            status,
+            status_text: String::new(),
+        };
+
+        assert!(status(503).is_transient());
+        assert!(!status(404).is_transient());
+        assert!(EngineError::Network {
+            resource: "assets.json".to_string(),
+            detail: None,
+        }
+        .is_transient());
+        assert!(!EngineError::Json {
+            resource: "assets.json".to_string(),
+            detail: None,
+        }
+        .is_transient());
+        assert!(!EngineError::ImageLoad {
+            source: "BG.png".to_string(),
+            detail: None,
+        }
+        .is_transient());
+        assert!(!EngineError::not_found("UI element not found").is_transient());
+    }
+
+    #[test]
+    fn failed_assets_are_all_named() {
+        let not_found = |resource: &str| EngineError::HttpStatus {
+            resource: resource.to_string(),
+            status: 404,
+            status_text: "Not Found".to_string(),
+        };
+        let err = EngineError::AssetLoad {
+            failed: vec![
+                ("rhb.png".to_string(), not_found("rhb.png")),
+                ("BG.png".to_string(), not_found("BG.png")),
+            ],
+        };
+
+        assert_eq!(err.to_string(), "Could not load rhb.png, BG.png");
+        assert_eq!(
+            EngineError::missing_asset("music track", "walk").to_string(),
+            "No music track named walk"
+        );
+    }
+
+    #[test]
+    fn game_errors_keep_their_context() {
+        let err = anyhow::anyhow!("not initialized").context("Could not continue");
+
+        assert_eq!(
+            EngineError::from(err),
+            EngineError::Game {
+                what: "Could not continue: not initialized".to_string()
+            }
+        );
+    }
+}
@@ -1,17 +1,25 @@
-use std::rc::Rc;
+use std::{collections::HashMap, rc::Rc};
 
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use futures::channel::mpsc::UnboundedReceiver;
-use rand::{thread_rng, Rng};
+use rand::{thread_rng, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
 use web_sys::HtmlImageElement;
 
 use crate::{
+    assets::{Assets, DataKey, ImageKey, SheetKey, SoundKey},
     browser,
     engine::{
-        self, Audio, Cell, Game, Image, KeyState, Point, Rect, Renderer, Sheet, Sound, SpriteSheet,
+        self, Animation, Audio, BroadPhase, Bus, Camera, Cell, ClipId, DrawOptions, Game, Image,
+        KeyState, Layer, Loading, Music, MusicTrackConfig, ParallaxLayer, ParallaxLayerConfig,
+        Point, Rect, RenderQueue, Renderer, Sound, SpriteSheet, Sweep,
     },
+    error::EngineError,
+    persistence::{self, HighScore, Leaderboard, LocalStorage, MemoryStorage, Storage},
     segments::{platform_and_stone, stone_and_platform},
+    synth::SynthParams,
 };
 
 use self::red_hat_boy_states::{
@@ -19,50 +27,85 @@ use self::red_hat_boy_states::{
     RedHatBoyState, Running, Sliding, SlidingEndState,
 };
 
-const HEIGHT: i16 = 600;
+const HEIGHT: f32 = 600.0;
+const WIDTH: f32 = 600.0;
+const MUSIC_FADE_IN: f64 = 2.0;
+const MUSIC_FADE_OUT: f64 = 1.5;
+// Running at this speed or faster brings in every music stem.
+const FULL_MUSIC_INTENSITY_SPEED: f32 = 8.0;
+// Ready and Walking share one track, so starting to run never restarts the
+// song. Only the stems above the song's intensity change.
+const MUSIC_TRACK: &str = "theme";
+const JUMP_PITCH_VARIATION: f32 = 0.08;
+const JUMP_VOLUME_VARIATION: f32 = 0.15;
+
+// Everything the game looks up by name from assets.json.
+const BACKGROUNDS: DataKey<Vec<ParallaxLayerConfig>> = DataKey::new("backgrounds.json");
+const SOUND_EFFECTS: DataKey<HashMap<String, SynthParams>> = DataKey::new("sfx.json");
+const MUSIC: DataKey<Vec<MusicTrackConfig>> = DataKey::new("music.json");
+const STONE_IMAGE: ImageKey = ImageKey("Stone.png");
+const BONE_IMAGE: ImageKey = ImageKey("Bone.svg");
+const TILES_SHEET: SheetKey = SheetKey("tiles.json");
+const TILES_IMAGE: ImageKey = ImageKey("tiles.png");
+const RHB_SHEET: SheetKey = SheetKey("rhb.json");
+const RHB_IMAGE: ImageKey = ImageKey("rhb.png");
+const JUMP_SOUND: SoundKey = SoundKey("SFX_Jump_23.mp3");
 
 pub struct RedHatBoy {
     state_machine: RedHatBoyStateMachine,
-    sprite_sheet: Sheet,
-    image: HtmlImageElement,
+    animation: Animation,
+    sprite_sheet: SpriteSheet,
+    audio: Audio,
+    sounds: RedHatBoySounds,
+}
+
+#[derive(Clone)]
+pub struct RedHatBoySounds {
+    jump: Sound,
+    land: Sound,
+    knock_out: Sound,
 }
 
 impl RedHatBoy {
-    fn new(sheet: Sheet, image: HtmlImageElement, audio: Audio, jump_sound: Sound) -> Self {
+    fn new(sprite_sheet: SpriteSheet, audio: Audio, sounds: RedHatBoySounds) -> Self {
         Self {
-            state_machine: RedHatBoyStateMachine::Idle(RedHatBoyState::new(audio, jump_sound)),
-            sprite_sheet: sheet,
-            image,
+            state_machine: RedHatBoyStateMachine::Idle(RedHatBoyState::new()),
+            animation: red_hat_boy_states::animation(sprite_sheet.atlas()),
+            sprite_sheet,
+            audio,
+            sounds,
         }
     }
 
     fn reset(boy: Self) -> Self {
-        RedHatBoy::new(
-            boy.sprite_sheet,
-            boy.image,
-            boy.state_machine.context().audio.clone(),
-            boy.state_machine.context().jump_sound.clone(),
-        )
+        RedHatBoy {
+            state_machine: RedHatBoyStateMachine::Idle(RedHatBoyState::new()),
+            ..boy
+        }
+    }
+
+    fn restore(mut boy: Self, state_machine: RedHatBoyStateMachine) -> Self {
+        boy.state_machine = state_machine;
+        boy
     }
 
     fn update(&mut self) {
-        self.state_machine = self.state_machine.clone().update();
+        let previous = self.state_machine.clone();
+        self.state_machine = previous.clone().update(&self.animation);
+        self.play_landing_sound(&previous);
     }
 
-    fn draw(&self, renderer: &Renderer) {
+    fn draw(&self, queue: &mut RenderQueue) {
         let sprite = self.current_sprite().expect("Cell not found");
 
-        renderer.draw_image(
-            &self.image,
-            &Rect::new_from_x_y(
-                sprite.frame.x,
-                sprite.frame.y,
-                sprite.frame.w,
-                sprite.frame.h,
-            ),
-            &self.destination_box(),
+        self.sprite_sheet.draw(
+            queue,
+            Layer::Actors,
+            sprite,
+            self.destination_box(),
+            DrawOptions::default(),
         );
-        renderer.draw_rect(&self.bounding_box());
+        queue.draw_rect(Layer::Debug, &self.bounding_box());
     }
 
     fn knocked_out(&self) -> bool {
@@ -70,50 +113,53 @@ impl RedHatBoy {
     }
 
     fn destination_box(&self) -> Rect {
-        let sprite = self.current_sprite().expect("Cell not found");
-
-        Rect::new_from_x_y(
-            self.state_machine.context().position.x + sprite.sprite_source_size.x,
-            self.state_machine.context().position.y + sprite.sprite_source_size.y,
-            sprite.frame.w,
-            sprite.frame.h,
-        )
+        self.current_sprite()
+            .expect("Cell not found")
+            .destination(self.state_machine.context().position)
     }
 
     fn bounding_box(&self) -> Rect {
-        const X_OFFSET: i16 = 16;
-        const Y_OFFSET: i16 = 14;
-        const WIDTH_OFFSET: i16 = 28;
+        // The empty margin of the sprite frame on each side of the boy.
+        const LEFT: f32 = 16.0;
+        const RIGHT: f32 = 12.0;
+        const TOP: f32 = 14.0;
+        const BOTTOM: f32 = 0.0;
+        let frame = self.destination_box();
         Rect::new_from_x_y(
-            self.destination_box().x() + X_OFFSET,
-            self.destination_box().y() + Y_OFFSET,
-            self.destination_box().width - WIDTH_OFFSET,
-            self.destination_box().height - Y_OFFSET,
+            frame.x() + LEFT,
+            frame.y() + TOP,
+            frame.width - LEFT - RIGHT,
+            frame.height - TOP - BOTTOM,
         )
     }
 
-    fn pos_y(&self) -> i16 {
-        self.state_machine.context().position.y
+    fn pos_x(&self) -> f32 {
+        self.state_machine.context().position.x
     }
 
-    fn walking_speed(&self) -> i16 {
-        self.state_machine.context().velocity.x
+    fn position(&self) -> Point {
+        self.state_machine.context().position
     }
 
-    fn velocity_y(&self) -> i16 {
-        self.state_machine.context().velocity.y
+    fn falling(&self) -> bool {
+        matches!(self.state_machine, RedHatBoyStateMachine::Falling(_))
     }
 
-    fn frame_name(&self) -> String {
-        format!(
-            "{} ({}).png",
-            self.state_machine.frame_name(),
-            (self.state_machine.context().frame / 3) + 1
-        )
+    fn walking_speed(&self) -> f32 {
+        self.state_machine.context().velocity.x
+    }
+
+    fn velocity_y(&self) -> f32 {
+        self.state_machine.context().velocity.y
     }
 
     fn current_sprite(&self) -> Option<&Cell> {
-        self.sprite_sheet.frames.get(&self.frame_name())
+        self.animation
+            .frame(
+                self.state_machine.clip(),
+                self.state_machine.context().elapsed,
+            )
+            .and_then(|frame| self.sprite_sheet.atlas().cell(frame))
     }
 
     fn run_right(&mut self) {
@@ -125,19 +171,51 @@ impl RedHatBoy {
     }
 
     fn jump(&mut self) {
+        let was_running = matches!(self.state_machine, RedHatBoyStateMachine::Running(_));
         self.state_machine = self.state_machine.clone().transition(Event::Jump);
+
+        if was_running && matches!(self.state_machine, RedHatBoyStateMachine::Jumping(_)) {
+            self.play_jump_sound();
+        }
+    }
+
+    fn play_jump_sound(&self) {
+        if let Err(error) = self.audio.play_sound(&self.sounds.jump) {
+            log!("Error playing jump sound: {:#?}", error);
+        }
     }
 
-    fn land_on(&mut self, position: i16) {
-        self.state_machine = self.state_machine.clone().transition(Event::Land(position));
+    fn land_on(&mut self, position: f32) {
+        let previous = self.state_machine.clone();
+        self.state_machine = previous.clone().transition(Event::Land(position));
+        self.play_landing_sound(&previous);
+    }
+
+    // The floor is landed on in update and platforms in land_on.
+    fn play_landing_sound(&self, previous: &RedHatBoyStateMachine) {
+        if self.state_machine.landed_from(previous) {
+            if let Err(error) = self.audio.play_sound(&self.sounds.land) {
+                log!("Error playing landing sound: {:#?}", error);
+            }
+        }
     }
 
     fn knock_out(&mut self) {
+        let was_falling = matches!(
+            self.state_machine,
+            RedHatBoyStateMachine::Falling(_) | RedHatBoyStateMachine::KnockedOut(_)
+        );
         self.state_machine = self.state_machine.clone().transition(Event::KnockOut);
+
+        if !was_falling && matches!(self.state_machine, RedHatBoyStateMachine::Falling(_)) {
+            if let Err(error) = self.audio.play_sound(&self.sounds.knock_out) {
+                log!("Error playing knock out sound: {:#?}", error);
+            }
+        }
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 enum RedHatBoyStateMachine {
     Idle(RedHatBoyState<Idle>),
     Running(RedHatBoyState<Running>),
@@ -147,16 +225,27 @@ enum RedHatBoyStateMachine {
     KnockedOut(RedHatBoyState<KnockedOut>),
 }
 
-pub enum Event {
+pub enum Event<'a> {
     Run,
     Jump,
     Slide,
-    Land(i16),
+    Land(f32),
     KnockOut,
-    Update,
+    Update(&'a Animation),
 }
 
 impl RedHatBoyStateMachine {
+    // Whether this state ends a jump that `previous` was in.
+    fn landed_from(&self, previous: &RedHatBoyStateMachine) -> bool {
+        matches!(
+            (previous, self),
+            (
+                RedHatBoyStateMachine::Jumping(_),
+                RedHatBoyStateMachine::Running(_)
+            )
+        )
+    }
+
     fn transition(self, event: Event) -> Self {
         match (self.clone(), event) {
             (RedHatBoyStateMachine::Idle(state), Event::Run) => state.run().into(),
@@ -171,11 +260,15 @@ impl RedHatBoyStateMachine {
             (RedHatBoyStateMachine::Sliding(state), Event::Land(position)) => {
                 state.land_on(position).into()
             }
-            (RedHatBoyStateMachine::Idle(state), Event::Update) => state.update().into(),
-            (RedHatBoyStateMachine::Running(state), Event::Update) => state.update().into(),
-            (RedHatBoyStateMachine::Jumping(state), Event::Update) => state.update().into(),
-            (RedHatBoyStateMachine::Sliding(state), Event::Update) => state.update().into(),
-            (RedHatBoyStateMachine::Falling(state), Event::Update) => state.update().into(),
+            (RedHatBoyStateMachine::Idle(state), Event::Update(_)) => state.update().into(),
+            (RedHatBoyStateMachine::Running(state), Event::Update(_)) => state.update().into(),
+            (RedHatBoyStateMachine::Jumping(state), Event::Update(_)) => state.update().into(),
+            (RedHatBoyStateMachine::Sliding(state), Event::Update(animation)) => {
+                state.update(animation).into()
+            }
+            (RedHatBoyStateMachine::Falling(state), Event::Update(animation)) => {
+                state.update(animation).into()
+            }
             (RedHatBoyStateMachine::Running(state), Event::KnockOut) => state.knock_out().into(),
             (RedHatBoyStateMachine::Jumping(state), Event::KnockOut) => state.knock_out().into(),
             (RedHatBoyStateMachine::Sliding(state), Event::KnockOut) => state.knock_out().into(),
@@ -183,22 +276,22 @@ impl RedHatBoyStateMachine {
         }
     }
 
-    fn update(self) -> Self {
-        self.transition(Event::Update)
+    fn update(self, animation: &Animation) -> Self {
+        self.transition(Event::Update(animation))
     }
 
     fn knocked_out(&self) -> bool {
         matches!(self, RedHatBoyStateMachine::KnockedOut(_))
     }
 
-    fn frame_name(&self) -> &str {
+    fn clip(&self) -> ClipId {
         match self {
-            RedHatBoyStateMachine::Idle(state) => state.frame_name(),
-            RedHatBoyStateMachine::Running(state) => state.frame_name(),
-            RedHatBoyStateMachine::Jumping(state) => state.frame_name(),
-            RedHatBoyStateMachine::Sliding(state) => state.frame_name(),
-            RedHatBoyStateMachine::Falling(state) => state.frame_name(),
-            RedHatBoyStateMachine::KnockedOut(state) => state.frame_name(),
+            RedHatBoyStateMachine::Idle(state) => state.clip(),
+            RedHatBoyStateMachine::Running(state) => state.clip(),
+            RedHatBoyStateMachine::Jumping(state) => state.clip(),
+            RedHatBoyStateMachine::Sliding(state) => state.clip(),
+            RedHatBoyStateMachine::Falling(state) => state.clip(),
+            RedHatBoyStateMachine::KnockedOut(state) => state.clip(),
         }
     }
 
@@ -278,29 +371,41 @@ impl From<FallingEndState> for RedHatBoyStateMachine {
 }
 
 mod red_hat_boy_states {
-    use crate::engine::{Audio, Point, Sound};
+    use serde::{Deserialize, Serialize};
+
+    use crate::engine::{Animation, Atlas, Clip, ClipId, LoopMode, Point, FRAME_SIZE};
 
     use super::HEIGHT;
 
-    const FLOOR: i16 = 479;
-    const PLAYER_HEIGHT: i16 = HEIGHT - FLOOR;
-    const STARTING_POINT: i16 = -20;
-    const IDLE_FRAME_NAME: &str = "Idle";
-    const IDLE_FRAMES: u8 = 29;
-    const RUN_FRAME_NAME: &str = "Run";
-    const RUN_FRAMES: u8 = 23;
-    const RUN_SPEED: i16 = 4;
-    const SLIDING_FRAME_NAME: &str = "Slide";
-    const SLIDING_FRAMES: u8 = 14;
-    const JUMPING_FRAME_NAME: &str = "Jump";
-    const JUMPING_FRAMES: u8 = 35;
-    const JUMP_SPEED: i16 = -25;
-    const GRAVITY: i16 = 1;
-    const TERMINAL_VELOCITY: i16 = 20;
-    const FALLING_FRAMES: u8 = 29;
-    const FALLING_FRAME_NAME: &str = "Dead";
-
-    #[derive(Clone)]
+    const FLOOR: f32 = 479.0;
+    const PLAYER_HEIGHT: f32 = HEIGHT - FLOOR;
+    const STARTING_POINT: f32 = -20.0;
+    const IDLE_CLIP: ClipId = ClipId(0);
+    const RUN_CLIP: ClipId = ClipId(1);
+    const RUN_SPEED: f32 = 4.0;
+    const SLIDING_CLIP: ClipId = ClipId(2);
+    const JUMPING_CLIP: ClipId = ClipId(3);
+    const JUMP_SPEED: f32 = -25.0;
+    const GRAVITY: f32 = 1.0;
+    const TERMINAL_VELOCITY: f32 = 20.0;
+    const FALLING_CLIP: ClipId = ClipId(4);
+    const FRAME_DURATION: f32 = 50.0;
+
+    pub fn animation(atlas: &Atlas) -> Animation {
+        [
+            (IDLE_CLIP, "Idle", LoopMode::Loop),
+            (RUN_CLIP, "Run", LoopMode::Loop),
+            (SLIDING_CLIP, "Slide", LoopMode::Once),
+            (JUMPING_CLIP, "Jump", LoopMode::Loop),
+            (FALLING_CLIP, "Dead", LoopMode::Once),
+        ]
+        .into_iter()
+        .fold(Animation::default(), |animation, (id, name, loop_mode)| {
+            animation.with_clip(id, Clip::from_atlas(atlas, name, FRAME_DURATION, loop_mode))
+        })
+    }
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
     pub struct RedHatBoyState<S> {
         context: RedHatBoyContext,
         _state: S,
@@ -313,42 +418,40 @@ mod red_hat_boy_states {
     }
 
     impl RedHatBoyState<Idle> {
-        pub fn new(audio: Audio, jump_sound: Sound) -> Self {
+        pub fn new() -> Self {
             RedHatBoyState {
                 context: RedHatBoyContext {
-                    frame: 0,
+                    elapsed: 0.0,
                     position: Point {
                         x: STARTING_POINT,
                         y: FLOOR,
                     },
-                    velocity: Point { x: 0, y: 0 },
-                    audio,
-                    jump_sound,
+                    velocity: Point { x: 0.0, y: 0.0 },
                 },
                 _state: Idle {},
             }
         }
 
         pub fn update(mut self) -> Self {
-            self.context = self.context.update(IDLE_FRAMES);
+            self.context = self.context.update();
             self
         }
 
         pub fn run(self) -> RedHatBoyState<Running> {
             RedHatBoyState {
-                context: self.context.reset_frames().run_right(),
+                context: self.context.restart_animation().run_right(),
                 _state: Running {},
             }
         }
 
-        pub fn frame_name(&self) -> &str {
-            IDLE_FRAME_NAME
+        pub fn clip(&self) -> ClipId {
+            IDLE_CLIP
         }
     }
 
     impl RedHatBoyState<Running> {
         pub fn update(mut self) -> Self {
-            self.context = self.context.update(RUN_FRAMES);
+            self.context = self.context.update();
             self
         }
 
@@ -357,20 +460,19 @@ mod red_hat_boy_states {
                 context: self
                     .context
                     .set_vertical_velocity(JUMP_SPEED)
-                    .reset_frames()
-                    .play_jump_sound(),
+                    .restart_animation(),
                 _state: Jumping {},
             }
         }
 
         pub fn slide(self) -> RedHatBoyState<Sliding> {
             RedHatBoyState {
-                context: self.context.reset_frames(),
+                context: self.context.restart_animation(),
                 _state: Sliding {},
             }
         }
 
-        pub fn land_on(self, position: i16) -> RedHatBoyState<Running> {
+        pub fn land_on(self, position: f32) -> RedHatBoyState<Running> {
             RedHatBoyState {
                 context: self.context.set_on(position),
                 _state: Running {},
@@ -379,13 +481,13 @@ mod red_hat_boy_states {
 
         pub fn knock_out(self) -> RedHatBoyState<Falling> {
             RedHatBoyState {
-                context: self.context.reset_frames().stop(),
+                context: self.context.restart_animation().stop(),
                 _state: Falling {},
             }
         }
 
-        pub fn frame_name(&self) -> &str {
-            RUN_FRAME_NAME
+        pub fn clip(&self) -> ClipId {
+            RUN_CLIP
         }
     }
 
@@ -395,10 +497,10 @@ mod red_hat_boy_states {
     }
 
     impl RedHatBoyState<Sliding> {
-        pub fn update(mut self) -> SlidingEndState {
-            self.context = self.context.update(SLIDING_FRAMES);
+        pub fn update(mut self, animation: &Animation) -> SlidingEndState {
+            self.context = self.context.update();
 
-            if self.context.frame >= SLIDING_FRAMES {
+            if animation.is_finished(SLIDING_CLIP, self.context.elapsed) {
                 SlidingEndState::Running(self.stand())
             } else {
                 SlidingEndState::Sliding(self)
@@ -407,12 +509,12 @@ mod red_hat_boy_states {
 
         pub fn stand(self) -> RedHatBoyState<Running> {
             RedHatBoyState {
-                context: self.context.reset_frames(),
+                context: self.context.restart_animation(),
                 _state: Running,
             }
         }
 
-        pub fn land_on(self, position: i16) -> RedHatBoyState<Sliding> {
+        pub fn land_on(self, position: f32) -> RedHatBoyState<Sliding> {
             RedHatBoyState {
                 context: self.context.set_on(position),
                 _state: Sliding {},
@@ -421,13 +523,13 @@ mod red_hat_boy_states {
 
         pub fn knock_out(self) -> RedHatBoyState<Falling> {
             RedHatBoyState {
-                context: self.context.reset_frames().stop(),
+                context: self.context.restart_animation().stop(),
                 _state: Falling {},
             }
         }
 
-        pub fn frame_name(&self) -> &str {
-            SLIDING_FRAME_NAME
+        pub fn clip(&self) -> ClipId {
+            SLIDING_CLIP
         }
     }
 
@@ -443,7 +545,7 @@ mod red_hat_boy_states {
 
     impl RedHatBoyState<Jumping> {
         pub fn update(mut self) -> JumpingEndState {
-            self.context = self.context.update(JUMPING_FRAMES);
+            self.context = self.context.update();
 
             if self.context.position.y >= FLOOR {
                 JumpingEndState::Landing(self.land_on(HEIGHT))
@@ -452,30 +554,30 @@ mod red_hat_boy_states {
             }
         }
 
-        pub fn land_on(self, position: i16) -> RedHatBoyState<Running> {
+        pub fn land_on(self, position: f32) -> RedHatBoyState<Running> {
             RedHatBoyState {
-                context: self.context.reset_frames().set_on(position),
+                context: self.context.restart_animation().set_on(position),
                 _state: Running {},
             }
         }
 
         pub fn knock_out(self) -> RedHatBoyState<Falling> {
             RedHatBoyState {
-                context: self.context.reset_frames().stop(),
+                context: self.context.restart_animation().stop(),
                 _state: Falling {},
             }
         }
 
-        pub fn frame_name(&self) -> &str {
-            JUMPING_FRAME_NAME
+        pub fn clip(&self) -> ClipId {
+            JUMPING_CLIP
         }
     }
 
     impl RedHatBoyState<Falling> {
-        pub fn update(mut self) -> FallingEndState {
-            self.context = self.context.update(FALLING_FRAMES);
+        pub fn update(mut self, animation: &Animation) -> FallingEndState {
+            self.context = self.context.update();
 
-            if self.context.frame >= FALLING_FRAMES {
+            if animation.is_finished(FALLING_CLIP, self.context.elapsed) {
                 FallingEndState::KnockedOut(self.knock_out())
             } else {
                 FallingEndState::Falling(self)
@@ -489,37 +591,33 @@ mod red_hat_boy_states {
             }
         }
 
-        pub fn frame_name(&self) -> &str {
-            FALLING_FRAME_NAME
+        pub fn clip(&self) -> ClipId {
+            FALLING_CLIP
         }
     }
 
     impl RedHatBoyState<KnockedOut> {
-        pub fn frame_name(&self) -> &str {
-            FALLING_FRAME_NAME
+        pub fn clip(&self) -> ClipId {
+            FALLING_CLIP
         }
     }
 
-    #[derive(Clone)]
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
     pub struct RedHatBoyContext {
-        pub frame: u8,
+        // Milliseconds into the current animation clip.
+        pub elapsed: f32,
         pub position: Point,
         pub velocity: Point,
-        pub audio: Audio,
-        pub jump_sound: Sound,
     }
 
     impl RedHatBoyContext {
-        pub fn update(mut self, frame_count: u8) -> Self {
-            if self.frame < frame_count {
-                self.frame += 1;
-            } else {
-                self.frame = 0;
-            }
+        pub fn update(mut self) -> Self {
+            self.elapsed += FRAME_SIZE;
 
             if self.velocity.y < TERMINAL_VELOCITY {
                 self.velocity.y += GRAVITY;
             }
+            self.position.x += self.velocity.x;
             self.position.y += self.velocity.y;
 
             if self.position.y > FLOOR {
@@ -534,65 +632,127 @@ mod red_hat_boy_states {
             self
         }
 
-        fn set_vertical_velocity(mut self, y: i16) -> Self {
+        fn set_vertical_velocity(mut self, y: f32) -> Self {
             self.velocity.y = y;
             self
         }
 
-        fn set_on(mut self, position: i16) -> Self {
+        fn set_on(mut self, position: f32) -> Self {
             let position = position - PLAYER_HEIGHT;
             self.position.y = position;
             self
         }
 
-        fn reset_frames(mut self) -> Self {
-            self.frame = 0;
+        fn restart_animation(mut self) -> Self {
+            self.elapsed = 0.0;
             self
         }
 
         fn stop(mut self) -> Self {
-            self.velocity.x = 0;
-            self.velocity.y = 0;
-            self
-        }
-
-        fn play_jump_sound(self) -> Self {
-            if let Err(error) = self.audio.play_sound(&self.jump_sound) {
-                log!("Error playing jump sound: {:#?}", error);
-            }
+            self.velocity.x = 0.0;
+            self.velocity.y = 0.0;
             self
         }
     }
 
-    #[derive(Copy, Clone)]
+    #[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
     pub struct Idle;
 
-    #[derive(Copy, Clone)]
+    #[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
     pub struct Running;
 
-    #[derive(Copy, Clone)]
+    #[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
     pub struct Sliding;
 
-    #[derive(Copy, Clone)]
+    #[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
     pub struct Jumping;
 
-    #[derive(Copy, Clone)]
+    #[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
     pub struct Falling;
 
-    #[derive(Copy, Clone)]
+    #[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
     pub struct KnockedOut;
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ObstacleKind {
+    Platform,
+    Barrier,
+    Collectible { points: u32 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Side {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+pub struct Contact {
+    // Index into the obstacle list the contact was found in.
+    obstacle: usize,
+    kind: ObstacleKind,
+    // The face of the obstacle that was touched.
+    side: Side,
+    bounds: Rect,
+}
+
+impl Contact {
+    fn new(obstacle: usize, kind: ObstacleKind, actor: &Rect, bounds: Rect) -> Self {
+        let side = if actor.y() < bounds.y() {
+            Side::Top
+        } else if actor.bottom() > bounds.bottom() {
+            Side::Bottom
+        } else if actor.center().x < bounds.center().x {
+            Side::Left
+        } else {
+            Side::Right
+        };
+
+        Self {
+            obstacle,
+            kind,
+            side,
+            bounds,
+        }
+    }
+}
+
 pub trait Obstacle {
-    fn check_intersection(&self, boy: &mut RedHatBoy);
-    fn draw(&self, renderer: &Renderer);
-    fn move_horizontal(&mut self, distance: i16);
-    fn right(&self) -> i16;
+    fn kind(&self) -> ObstacleKind;
+    fn check_intersection(&self, bounding_box: &Rect) -> Option<Rect>;
+    fn draw(&self, queue: &mut RenderQueue);
+    fn bounding_box(&self) -> Rect;
+    // The earliest surface a rect moving by `displacement` runs into.
+    fn sweep(&self, _bounding_box: &Rect, _displacement: Point) -> Option<(Sweep, Rect)> {
+        None
+    }
+    fn right(&self) -> f32;
+    fn snapshot(&self) -> ObstacleSnapshot;
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum ObstacleSnapshot {
+    Barrier {
+        position: Point,
+    },
+    Collectible {
+        position: Point,
+        points: u32,
+    },
+    Platform {
+        sprites: Vec<String>,
+        bounding_boxes: Vec<Rect>,
+        position: Point,
+    },
 }
 
 pub struct Platform {
     sheet: Rc<SpriteSheet>,
     bounding_boxes: Vec<Rect>,
+    bounds: Rect,
+    sprite_names: Vec<String>,
     sprites: Vec<Cell>,
     position: Point,
 }
@@ -610,20 +770,20 @@ impl Platform {
             .collect();
         let bounding_boxes = bounding_boxes
             .iter()
-            .map(|bounding_box| {
-                Rect::new_from_x_y(
-                    bounding_box.x() + position.x,
-                    bounding_box.y() + position.y,
-                    bounding_box.width,
-                    bounding_box.height,
-                )
-            })
-            .collect();
+            .map(|bounding_box| bounding_box.translate(position))
+            .collect::<Vec<Rect>>();
+        let bounds = bounding_boxes
+            .iter()
+            .cloned()
+            .reduce(|bounds, bounding_box| bounds.union(&bounding_box))
+            .unwrap_or_default();
         Self {
             sheet,
             position,
+            sprite_names: sprite_names.iter().map(|name| name.to_string()).collect(),
             sprites,
             bounding_boxes,
+            bounds,
         }
     }
 
@@ -633,58 +793,81 @@ impl Platform {
 }
 
 impl Obstacle for Platform {
-    fn check_intersection(&self, boy: &mut RedHatBoy) {
-        if let Some(box_to_land_on) = self
-            .bounding_boxes()
-            .iter()
-            .find(|&bounding_box| boy.bounding_box().intersects(bounding_box))
-        {
-            if boy.velocity_y() > 0 && boy.pos_y() < self.position.y {
-                boy.land_on(box_to_land_on.y());
-            } else {
-                boy.knock_out();
-            }
+    fn kind(&self) -> ObstacleKind {
+        ObstacleKind::Platform
+    }
+
+    fn check_intersection(&self, bounding_box: &Rect) -> Option<Rect> {
+        if !bounding_box.intersects(&self.bounds) {
+            return None;
         }
+
+        self.bounding_boxes()
+            .iter()
+            .find(|&platform_box| bounding_box.intersects(platform_box))
+            .cloned()
     }
 
-    fn draw(&self, renderer: &Renderer) {
-        let mut x = 0;
+    fn draw(&self, queue: &mut RenderQueue) {
+        let mut x = 0.0;
         self.sprites.iter().for_each(|sprite| {
             self.sheet.draw(
-                renderer,
-                &Rect::new_from_x_y(
-                    sprite.frame.x,
-                    sprite.frame.y,
-                    sprite.frame.w,
-                    sprite.frame.h,
-                ),
-                &Rect::new_from_x_y(
-                    self.position.x + x,
-                    self.position.y,
-                    sprite.frame.w,
-                    sprite.frame.h,
-                ),
+                queue,
+                Layer::Obstacles,
+                sprite,
+                sprite.destination(Point {
+                    x: self.position.x + x,
+                    y: self.position.y,
+                }),
+                DrawOptions::default(),
             );
-            x += sprite.frame.w;
+            x += f32::from(sprite.frame.w);
         });
         self.bounding_boxes()
             .iter()
-            .for_each(|bounding_box| renderer.draw_rect(bounding_box));
+            .for_each(|bounding_box| queue.draw_rect(Layer::Debug, bounding_box));
     }
 
-    fn move_horizontal(&mut self, x: i16) {
-        self.position.x += x;
-        self.bounding_boxes.iter_mut().for_each(|bounding_box| {
-            bounding_box.set_x(bounding_box.position.x + x);
-        });
+    fn bounding_box(&self) -> Rect {
+        self.bounds.clone()
+    }
+
+    fn sweep(&self, bounding_box: &Rect, displacement: Point) -> Option<(Sweep, Rect)> {
+        self.bounding_boxes()
+            .iter()
+            .filter_map(|platform_box| {
+                bounding_box
+                    .sweep(displacement, platform_box)
+                    .map(|sweep| (sweep, platform_box.clone()))
+            })
+            .min_by(|(first, _), (second, _)| first.time.total_cmp(&second.time))
     }
 
-    fn right(&self) -> i16 {
+    fn right(&self) -> f32 {
         self.bounding_boxes()
             .last()
             .unwrap_or(&Rect::default())
             .right()
     }
+
+    fn snapshot(&self) -> ObstacleSnapshot {
+        ObstacleSnapshot::Platform {
+            sprites: self.sprite_names.clone(),
+            bounding_boxes: self
+                .bounding_boxes()
+                .iter()
+                .map(|bounding_box| {
+                    Rect::new_from_x_y(
+                        bounding_box.x() - self.position.x,
+                        bounding_box.y() - self.position.y,
+                        bounding_box.width,
+                        bounding_box.height,
+                    )
+                })
+                .collect(),
+            position: self.position,
+        }
+    }
 }
 
 pub struct Barrier {
@@ -698,80 +881,248 @@ impl Barrier {
 }
 
 impl Obstacle for Barrier {
-    fn check_intersection(&self, boy: &mut RedHatBoy) {
-        if boy.bounding_box().intersects(self.image.bounding_box()) {
-            boy.knock_out();
+    fn kind(&self) -> ObstacleKind {
+        ObstacleKind::Barrier
+    }
+
+    fn check_intersection(&self, bounding_box: &Rect) -> Option<Rect> {
+        bounding_box
+            .intersects(self.image.bounding_box())
+            .then(|| self.image.bounding_box().clone())
+    }
+
+    fn draw(&self, queue: &mut RenderQueue) {
+        self.image.draw(queue, Layer::Obstacles);
+    }
+
+    fn bounding_box(&self) -> Rect {
+        self.image.bounding_box().clone()
+    }
+
+    fn right(&self) -> f32 {
+        self.image.right()
+    }
+
+    fn snapshot(&self) -> ObstacleSnapshot {
+        ObstacleSnapshot::Barrier {
+            position: self.image.bounding_box().position,
         }
     }
+}
 
-    fn draw(&self, renderer: &Renderer) {
-        self.image.draw(renderer);
+pub struct Collectible {
+    image: Image,
+    points: u32,
+}
+
+impl Collectible {
+    pub fn new(image: Image, points: u32) -> Self {
+        Self { image, points }
+    }
+}
+
+impl Obstacle for Collectible {
+    fn kind(&self) -> ObstacleKind {
+        ObstacleKind::Collectible {
+            points: self.points,
+        }
+    }
+
+    fn check_intersection(&self, bounding_box: &Rect) -> Option<Rect> {
+        bounding_box
+            .intersects(self.image.bounding_box())
+            .then(|| self.image.bounding_box().clone())
     }
 
-    fn move_horizontal(&mut self, x: i16) {
-        self.image.move_horizontal(x);
+    fn draw(&self, queue: &mut RenderQueue) {
+        self.image.draw(queue, Layer::Obstacles);
     }
 
-    fn right(&self) -> i16 {
+    fn bounding_box(&self) -> Rect {
+        self.image.bounding_box().clone()
+    }
+
+    fn right(&self) -> f32 {
         self.image.right()
     }
+
+    fn snapshot(&self) -> ObstacleSnapshot {
+        ObstacleSnapshot::Collectible {
+            position: self.image.bounding_box().position,
+            points: self.points,
+        }
+    }
 }
 
 pub struct Walk {
     obstacle_sheet: Rc<SpriteSheet>,
     boy: RedHatBoy,
-    backgrounds: [Image; 2],
+    backgrounds: Vec<ParallaxLayer>,
     obstacles: Vec<Box<dyn Obstacle>>,
+    broad_phase: BroadPhase,
     stone: HtmlImageElement,
-    timeline: i16,
+    bone: HtmlImageElement,
+    audio: Audio,
+    pickup_sound: Sound,
+    music: Music,
+    timeline: f32,
+    camera: Camera,
+    score: u32,
+    seed: u64,
+    rng: ChaCha8Rng,
+    leaderboard: Leaderboard,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct WalkSnapshot {
+    boy: RedHatBoyStateMachine,
+    obstacles: Vec<ObstacleSnapshot>,
+    timeline: f32,
+    score: u32,
+    seed: u64,
+    rng: ChaCha8Rng,
 }
 
 impl Walk {
     fn reset(walk: Self) -> Self {
-        let starting_obstacles =
-            stone_and_platform(walk.stone.clone(), walk.obstacle_sheet.clone(), 0);
+        let starting_obstacles = stone_and_platform(
+            walk.stone.clone(),
+            walk.bone.clone(),
+            walk.obstacle_sheet.clone(),
+            0.0,
+        );
         let timeline = rightmost(&starting_obstacles);
+        let seed = thread_rng().gen();
 
         Walk {
             boy: RedHatBoy::reset(walk.boy),
             backgrounds: walk.backgrounds,
+            broad_phase: broad_phase(&starting_obstacles),
             obstacles: starting_obstacles,
             obstacle_sheet: walk.obstacle_sheet,
             stone: walk.stone,
+            bone: walk.bone,
+            audio: walk.audio,
+            pickup_sound: walk.pickup_sound,
+            music: walk.music,
             timeline,
+            camera: Camera::default(),
+            score: 0,
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            leaderboard: walk.leaderboard,
+        }
+    }
+
+    fn restore(walk: Self, snapshot: WalkSnapshot) -> Self {
+        let obstacles: Vec<Box<dyn Obstacle>> = snapshot
+            .obstacles
+            .into_iter()
+            .map(|obstacle| -> Box<dyn Obstacle> {
+                match obstacle {
+                    ObstacleSnapshot::Barrier { position } => {
+                        Box::new(Barrier::new(Image::new(walk.stone.clone(), position)))
+                    }
+                    ObstacleSnapshot::Collectible { position, points } => Box::new(
+                        Collectible::new(Image::new(walk.bone.clone(), position), points),
+                    ),
+                    ObstacleSnapshot::Platform {
+                        sprites,
+                        bounding_boxes,
+                        position,
+                    } => {
+                        let sprite_names: Vec<&str> = sprites.iter().map(String::as_str).collect();
+                        Box::new(Platform::new(
+                            walk.obstacle_sheet.clone(),
+                            position,
+                            &sprite_names,
+                            &bounding_boxes,
+                        ))
+                    }
+                }
+            })
+            .collect();
+
+        let boy = RedHatBoy::restore(walk.boy, snapshot.boy);
+        let mut camera = Camera::default();
+        camera.follow_horizontal(boy.pos_x(), BOY_SCREEN_X);
+
+        Walk {
+            boy,
+            backgrounds: walk.backgrounds,
+            broad_phase: broad_phase(&obstacles),
+            obstacles,
+            obstacle_sheet: walk.obstacle_sheet,
+            stone: walk.stone,
+            bone: walk.bone,
+            audio: walk.audio,
+            pickup_sound: walk.pickup_sound,
+            music: walk.music,
+            timeline: snapshot.timeline,
+            camera,
+            score: snapshot.score,
+            seed: snapshot.seed,
+            rng: snapshot.rng,
+            leaderboard: walk.leaderboard,
+        }
+    }
+
+    fn snapshot(&self) -> WalkSnapshot {
+        WalkSnapshot {
+            boy: self.boy.state_machine.clone(),
+            obstacles: self
+                .obstacles
+                .iter()
+                .map(|obstacle| obstacle.snapshot())
+                .collect(),
+            timeline: self.timeline,
+            score: self.score,
+            seed: self.seed,
+            rng: self.rng.clone(),
         }
     }
 
     fn draw(&self, renderer: &Renderer) {
+        let mut queue = RenderQueue::default();
         self.backgrounds.iter().for_each(|background| {
-            background.draw(renderer);
+            background.draw(&mut queue, &self.camera, renderer.width());
         });
-        self.boy.draw(renderer);
+        self.boy.draw(&mut queue);
         self.obstacles.iter().for_each(|obstacle| {
-            obstacle.draw(renderer);
+            obstacle.draw(&mut queue);
         });
+        renderer.draw_with_camera(&self.camera, |renderer| queue.flush(renderer));
     }
 
     fn knocked_out(&self) -> bool {
         self.boy.knocked_out()
     }
 
-    fn velocity(&self) -> i16 {
-        -self.boy.walking_speed()
+    fn velocity(&self) -> f32 {
+        self.boy.walking_speed()
+    }
+
+    fn knock_out_boy(&mut self) {
+        let was_falling = self.boy.falling();
+        self.boy.knock_out();
+        if !was_falling && self.boy.falling() {
+            self.camera.shake(KNOCK_OUT_SHAKE, KNOCK_OUT_SHAKE_TICKS);
+        }
     }
 
     fn generate_next_segment(&mut self) {
-        let mut rng = thread_rng();
-        let next_segment = rng.gen_range(0..2);
+        let next_segment = self.rng.gen_range(0..2);
 
         let mut next_obstacles = match next_segment {
             0 => stone_and_platform(
                 self.stone.clone(),
+                self.bone.clone(),
                 self.obstacle_sheet.clone(),
                 self.timeline + OBSTACLE_BUFFER,
             ),
             1 => platform_and_stone(
                 self.stone.clone(),
+                self.bone.clone(),
                 self.obstacle_sheet.clone(),
                 self.timeline + OBSTACLE_BUFFER,
             ),
@@ -780,6 +1131,87 @@ impl Walk {
 
         self.timeline = rightmost(&next_obstacles);
         self.obstacles.append(&mut next_obstacles);
+        self.broad_phase = broad_phase(&self.obstacles);
+    }
+
+    fn contacts(&self, bounding_box: &Rect) -> Vec<Contact> {
+        self.broad_phase
+            .query(bounding_box)
+            .into_iter()
+            .filter_map(|index| {
+                let obstacle = &self.obstacles[index];
+                obstacle
+                    .check_intersection(bounding_box)
+                    .map(|bounds| Contact::new(index, obstacle.kind(), bounding_box, bounds))
+            })
+            .collect()
+    }
+
+    // Lands the boy on the first platform top he crossed since
+    // `previous_position`, however far he moved in one tick.
+    fn land_on_swept_platform(&mut self, previous_position: Point) {
+        if self.boy.velocity_y() <= 0.0 {
+            return;
+        }
+
+        let end = self.boy.bounding_box();
+        let displacement = self.boy.position() - previous_position;
+        let start = end.translate(displacement * -1.0);
+        let swept_area = start.union(&end);
+
+        let landing = self
+            .broad_phase
+            .query(&swept_area)
+            .into_iter()
+            .filter_map(|index| self.obstacles[index].sweep(&start, displacement))
+            .filter(|(sweep, _)| sweep.normal.y < 0.0)
+            .min_by(|(first, _), (second, _)| first.time.total_cmp(&second.time));
+
+        if let Some((_, surface)) = landing {
+            self.boy.land_on(surface.y());
+        }
+    }
+
+    fn resolve_collisions(&mut self, previous_position: Point) {
+        self.land_on_swept_platform(previous_position);
+
+        let contacts = self.contacts(&self.boy.bounding_box());
+        contacts.iter().for_each(|contact| match contact.kind {
+            ObstacleKind::Platform => {
+                if contact.side == Side::Top && self.boy.velocity_y() > 0.0 {
+                    self.boy.land_on(contact.bounds.y());
+                } else {
+                    self.knock_out_boy();
+                }
+            }
+            ObstacleKind::Barrier => self.knock_out_boy(),
+            ObstacleKind::Collectible { .. } => {}
+        });
+
+        let (points, pickup_x) = collect(&mut self.obstacles, &contacts);
+        if let Some(pickup_x) = pickup_x {
+            self.score += points;
+            self.broad_phase = broad_phase(&self.obstacles);
+            let pan = engine::stereo_pan(pickup_x - self.camera.view().x, WIDTH);
+            if let Err(err) = self.audio.play_sound_panned(&self.pickup_sound, pan) {
+                log!("Error playing pickup sound: {:#?}", err);
+            }
+        }
+    }
+
+    fn record_score(&mut self) -> Option<usize> {
+        let rank = self.leaderboard.submit(HighScore {
+            score: self.score,
+            seed: self.seed,
+            date: browser::iso_date(),
+        });
+
+        if rank.is_some() {
+            if let Err(err) = self.leaderboard.save() {
+                error!("Could not save high scores {:#?}", err);
+            }
+        }
+        rank
     }
 }
 
@@ -791,6 +1223,7 @@ struct Ready;
 struct Walking;
 struct GameOver {
     new_game_event: UnboundedReceiver<()>,
+    new_record: Option<usize>,
 }
 
 impl GameOver {
@@ -860,32 +1293,34 @@ impl WalkTheDogState<Walking> {
             self.walk.boy.slide();
         }
 
+        let previous_position = self.walk.boy.position();
         self.walk.boy.update();
+        self.walk
+            .camera
+            .follow_horizontal(self.walk.boy.pos_x(), BOY_SCREEN_X);
+        self.walk.camera.update();
 
         let walking_speed = self.walk.velocity();
-        let [first_background, second_background] = &mut self.walk.backgrounds;
-        first_background.move_horizontal(walking_speed);
-        second_background.move_horizontal(walking_speed);
-
-        if first_background.right() < 0 {
-            first_background.set_x(second_background.right());
+        self.walk.score += walking_speed.abs().round() as u32;
+        if let Err(err) = self
+            .walk
+            .music
+            .set_intensity(walking_speed.abs() / FULL_MUSIC_INTENSITY_SPEED)
+        {
+            error!("Could not change music intensity {:#?}", err);
         }
-
-        if second_background.right() < 0 {
-            second_background.set_x(first_background.right());
+        let camera_x = self.walk.camera.x();
+        let obstacle_count = self.walk.obstacles.len();
+        self.walk
+            .obstacles
+            .retain(|obstacle| obstacle.right() > camera_x);
+        if self.walk.obstacles.len() != obstacle_count {
+            self.walk.broad_phase = broad_phase(&self.walk.obstacles);
         }
+        self.walk.resolve_collisions(previous_position);
 
-        self.walk.obstacles.retain(|obstacle| obstacle.right() > 0);
-
-        self.walk.obstacles.iter_mut().for_each(|obstacle| {
-            obstacle.move_horizontal(walking_speed);
-            obstacle.check_intersection(&mut self.walk.boy);
-        });
-
-        if self.walk.timeline < TIMELINE_MINIMUM {
+        if self.walk.timeline - camera_x < TIMELINE_MINIMUM {
             self.walk.generate_next_segment();
-        } else {
-            self.walk.timeline += walking_speed;
         }
 
         if self.walk.knocked_out() {
@@ -895,22 +1330,35 @@ impl WalkTheDogState<Walking> {
         }
     }
 
-    fn end_game(self) -> WalkTheDogState<GameOver> {
-        let receiver = browser::draw_ui("<button id='new_game'>New Game</button>")
-            .and_then(|_unit| browser::find_html_element_by_id("new_game"))
-            .map(engine::add_click_handler)
-            .unwrap();
+    fn end_game(mut self) -> WalkTheDogState<GameOver> {
+        let new_record = self.walk.record_score();
+        if let Err(err) = self.walk.music.stop(MUSIC_FADE_OUT) {
+            error!("Could not fade out music {:#?}", err);
+        }
+        WalkTheDogState::<GameOver>::new(self.walk, new_record)
+    }
+}
+
+impl WalkTheDogState<GameOver> {
+    fn new(walk: Walk, new_record: Option<usize>) -> Self {
+        let receiver = browser::draw_ui(&game_over_ui(
+            walk.score,
+            walk.leaderboard.scores(),
+            new_record,
+        ))
+        .and_then(|_unit| browser::find_html_element_by_id("new_game"))
+        .map(engine::add_click_handler)
+        .unwrap();
 
         WalkTheDogState {
             _state: GameOver {
                 new_game_event: receiver,
+                new_record,
             },
-            walk: self.walk,
+            walk,
         }
     }
-}
 
-impl WalkTheDogState<GameOver> {
     fn update(mut self) -> GameOverEndState {
         if self._state.new_game_pressed() {
             GameOverEndState::Complete(self.new_game())
@@ -919,10 +1367,17 @@ impl WalkTheDogState<GameOver> {
         }
     }
 
-    fn new_game(self) -> WalkTheDogState<Ready> {
+    fn new_game(mut self) -> WalkTheDogState<Ready> {
         if let Err(err) = browser::hide_ui() {
             error!("Error hiding the browser {:#?}", err);
         }
+        let music = &mut self.walk.music;
+        if let Err(err) = music
+            .set_intensity(0.0)
+            .and_then(|_unit| music.play(MUSIC_TRACK, MUSIC_FADE_IN))
+        {
+            error!("Could not restart music {:#?}", err);
+        }
 
         WalkTheDogState {
             _state: Ready,
@@ -939,7 +1394,7 @@ enum WalkTheDogStateMachine {
 
 impl WalkTheDogStateMachine {
     fn new(walk: Walk) -> Self {
-        WalkTheDogStateMachine::Ready(WalkTheDogState::new(walk))
+        WalkTheDogStateMachine::Ready(WalkTheDogState::<Ready>::new(walk))
     }
 
     fn update(self, keystate: &KeyState) -> Self {
@@ -958,6 +1413,66 @@ impl WalkTheDogStateMachine {
             WalkTheDogStateMachine::GameOver(state) => state.draw(renderer),
         }
     }
+
+    fn snapshot(&self) -> GameSnapshot {
+        match self {
+            WalkTheDogStateMachine::Ready(state) => GameSnapshot::Ready(state.walk.snapshot()),
+            WalkTheDogStateMachine::Walking(state) => GameSnapshot::Walking(state.walk.snapshot()),
+            WalkTheDogStateMachine::GameOver(state) => GameSnapshot::GameOver {
+                walk: state.walk.snapshot(),
+                new_record: state._state.new_record,
+            },
+        }
+    }
+
+    // Picks the music back up to match the restored state.
+    fn restore(self, snapshot: GameSnapshot) -> Self {
+        let mut walk = match self {
+            WalkTheDogStateMachine::Ready(state) => state.walk,
+            WalkTheDogStateMachine::Walking(state) => state.walk,
+            WalkTheDogStateMachine::GameOver(state) => {
+                if let Err(err) = browser::hide_ui() {
+                    error!("Error hiding the browser {:#?}", err);
+                }
+                state.walk
+            }
+        };
+
+        let music = match snapshot {
+            GameSnapshot::Ready(_) | GameSnapshot::Walking(_) => {
+                walk.music.play(MUSIC_TRACK, MUSIC_FADE_IN)
+            }
+            GameSnapshot::GameOver { .. } => walk.music.stop(MUSIC_FADE_OUT),
+        };
+        if let Err(err) = music {
+            error!("Could not switch music {:#?}", err);
+        }
+
+        match snapshot {
+            GameSnapshot::Ready(snapshot) => {
+                WalkTheDogState::<Ready>::new(Walk::restore(walk, snapshot)).into()
+            }
+            GameSnapshot::Walking(snapshot) => WalkTheDogState {
+                _state: Walking,
+                walk: Walk::restore(walk, snapshot),
+            }
+            .into(),
+            GameSnapshot::GameOver {
+                walk: snapshot,
+                new_record,
+            } => WalkTheDogState::<GameOver>::new(Walk::restore(walk, snapshot), new_record).into(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum GameSnapshot {
+    Ready(WalkSnapshot),
+    Walking(WalkSnapshot),
+    GameOver {
+        walk: WalkSnapshot,
+        new_record: Option<usize>,
+    },
 }
 
 impl From<WalkTheDogState<Ready>> for WalkTheDogStateMachine {
@@ -1007,61 +1522,195 @@ impl From<GameOverEndState> for WalkTheDogStateMachine {
 
 pub struct WalkTheDog {
     machine: Option<WalkTheDogStateMachine>,
+    services: Option<Services>,
+    // Whatever loaded before a failure, so a retry only fetches the rest.
+    assets: Assets,
+    ticks: u32,
+}
+
+// Everything initialize needs that isn't an asset. Browsers only allow a
+// handful of audio contexts, and the unlock listeners and mixer bindings
+// would stack up, so these outlive a failed load and its retry.
+#[derive(Clone)]
+struct Services {
+    storage: Rc<dyn Storage>,
+    audio: Audio,
 }
 
 impl WalkTheDog {
     pub fn new() -> Self {
-        WalkTheDog { machine: None }
+        WalkTheDog {
+            machine: None,
+            services: None,
+            assets: Assets::default(),
+            ticks: 0,
+        }
+    }
+
+    fn services(&mut self) -> Result<Services, EngineError> {
+        if let Some(services) = &self.services {
+            return Ok(services.clone());
+        }
+
+        let storage: Rc<dyn Storage> = match LocalStorage::new() {
+            Ok(storage) => Rc::new(storage),
+            Err(err) => {
+                error!("Could not open local storage {:#?}", err);
+                Rc::new(MemoryStorage::default())
+            }
+        };
+        let audio = Audio::new()?;
+        match persistence::load_mixer_settings(storage.as_ref()) {
+            Ok(settings) => audio.apply_settings(settings),
+            Err(err) => error!("Could not load mixer settings {:#?}", err),
+        }
+        if let Err(err) = bind_mixer_controls(&audio, storage.clone()) {
+            error!("Could not bind mixer controls {:#?}", err);
+        }
+
+        let services = Services { storage, audio };
+        self.services = Some(services.clone());
+        Ok(services)
+    }
+
+    pub fn snapshot(&self) -> Option<GameSnapshot> {
+        self.machine.as_ref().map(|machine| machine.snapshot())
+    }
+
+    pub fn restore(&mut self, snapshot: GameSnapshot) -> Result<()> {
+        let machine = self
+            .machine
+            .take()
+            .ok_or_else(|| anyhow!("Error: Game is not initialized!"))?;
+        self.machine = Some(machine.restore(snapshot));
+        Ok(())
+    }
+
+    // Saved every so often rather than on unload, which browsers don't
+    // reliably leave time for. A reload then continues the run from here.
+    fn save_snapshot(&self) {
+        if let (Some(snapshot), Some(services)) = (self.snapshot(), &self.services) {
+            if let Err(err) = persistence::save_snapshot(services.storage.as_ref(), &snapshot) {
+                error!("Could not save the game {:#?}", err);
+            }
+        }
     }
 }
 
-const TIMELINE_MINIMUM: i16 = 1000;
-const OBSTACLE_BUFFER: i16 = 20;
+const TIMELINE_MINIMUM: f32 = 1000.0;
+const OBSTACLE_BUFFER: f32 = 20.0;
+const BOY_SCREEN_X: f32 = -20.0;
+const KNOCK_OUT_SHAKE: f32 = 6.0;
+const KNOCK_OUT_SHAKE_TICKS: u8 = 20;
+// Updates between saved snapshots, about a second.
+const SNAPSHOT_INTERVAL: u32 = 60;
 
 #[async_trait(?Send)]
 impl Game for WalkTheDog {
-    async fn initialize(&mut self) -> Result<Box<dyn Game>> {
+    async fn initialize(&mut self, loading: &Loading) -> Result<Box<dyn Game>, EngineError> {
         match self.machine {
             None => {
-                let json = browser::fetch_json("rhb.json").await??;
-                let background = engine::load_image("BG.png").await?;
-                let stone = engine::load_image("Stone.png").await?;
-                let tiles = browser::fetch_json("tiles.json").await??;
+                let Services { storage, audio } = self.services()?;
+                self.assets
+                    .load("assets.json", &audio, |progress| {
+                        loading.set_progress(progress.fraction())
+                    })
+                    .await?;
+                let assets = &self.assets;
+
+                let layers = assets.data(BACKGROUNDS)?;
+                let backgrounds = layers
+                    .into_iter()
+                    .map(|layer| {
+                        assets.image(ImageKey(&layer.image)).map(|element| {
+                            ParallaxLayer::new(
+                                element,
+                                layer.scroll_factor,
+                                layer.offset_y,
+                                layer.layer,
+                            )
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                let stone = assets.image(STONE_IMAGE)?;
+                let bone = assets.image(BONE_IMAGE)?;
                 let sprite_sheet = Rc::new(SpriteSheet::new(
-                    tiles,
-                    engine::load_image("tiles.png").await?,
+                    assets.sheet(TILES_SHEET)?,
+                    assets.image(TILES_IMAGE)?,
                 ));
-                let audio = Audio::new()?;
-                let sound = audio.load_sound("SFX_Jump_23.mp3").await?;
-                let background_music = audio.load_sound("background_song.mp3").await?;
-                audio.play_looping_sound(&background_music)?;
-
-                let rhb = RedHatBoy::new(json, engine::load_image("rhb.png").await?, audio, sound);
-                let background_width = background.width() as i16;
-                let starting_obstacles = stone_and_platform(stone.clone(), sprite_sheet.clone(), 0);
+                let sound = assets
+                    .sound(JUMP_SOUND)?
+                    .with_variation(JUMP_PITCH_VARIATION, JUMP_VOLUME_VARIATION);
+                let effects = assets.data(SOUND_EFFECTS)?;
+                let synthesize = |name: &str| {
+                    effects
+                        .get(name)
+                        .ok_or_else(|| EngineError::missing_asset("sound effect", name))
+                        .and_then(|params| audio.synthesize(params))
+                };
+                let sounds = RedHatBoySounds {
+                    jump: sound,
+                    land: synthesize("land")?,
+                    knock_out: synthesize("knock_out")?,
+                };
+                let pickup_sound = synthesize("pickup")?;
+                let tracks = assets.data(MUSIC)?;
+                // Stems named in sfx.json are synthesized, and last exactly a
+                // beat so they loop in time with the recorded ones.
+                let mut music = Music::with_tracks(audio.clone(), tracks, |file| {
+                    if effects.contains_key(file) {
+                        synthesize(file)
+                    } else {
+                        assets.sound(SoundKey(file))
+                    }
+                })?;
+                music.play(MUSIC_TRACK, MUSIC_FADE_IN)?;
+
+                let rhb = RedHatBoy::new(
+                    SpriteSheet::new(assets.sheet(RHB_SHEET)?, assets.image(RHB_IMAGE)?),
+                    audio.clone(),
+                    sounds,
+                );
+                let starting_obstacles =
+                    stone_and_platform(stone.clone(), bone.clone(), sprite_sheet.clone(), 0.0);
                 let timeline = rightmost(&starting_obstacles);
+                let leaderboard = Leaderboard::load(storage.clone()).unwrap_or_else(|err| {
+                    error!("Could not load high scores {:#?}", err);
+                    Leaderboard::new(Rc::new(MemoryStorage::default()))
+                });
+                let seed = thread_rng().gen();
                 let machine = WalkTheDogStateMachine::new(Walk {
                     boy: rhb,
-                    backgrounds: [
-                        Image::new(background.clone(), Point { x: 0, y: 0 }),
-                        Image::new(
-                            background,
-                            Point {
-                                x: background_width,
-                                y: 0,
-                            },
-                        ),
-                    ],
+                    backgrounds,
+                    broad_phase: broad_phase(&starting_obstacles),
                     obstacles: starting_obstacles,
                     obstacle_sheet: sprite_sheet,
                     stone,
+                    bone,
+                    audio,
+                    pickup_sound,
+                    music,
                     timeline,
+                    camera: Camera::default(),
+                    score: 0,
+                    seed,
+                    rng: ChaCha8Rng::seed_from_u64(seed),
+                    leaderboard,
                 });
-                Ok(Box::new(WalkTheDog {
+                let mut game = WalkTheDog {
                     machine: Some(machine),
-                }))
+                    services: self.services.take(),
+                    assets: Assets::default(),
+                    ticks: 0,
+                };
+                match persistence::load_snapshot(storage.as_ref()) {
+                    Ok(Some(snapshot)) => game.restore(snapshot)?,
+                    Ok(None) => {}
+                    Err(err) => error!("Could not continue the last game {:#?}", err),
+                }
+                Ok(Box::new(game))
             }
-            Some(_) => Err(anyhow!("Error: Game is already initialized!")),
+            Some(_) => Err(anyhow!("Error: Game is already initialized!").into()),
         }
     }
 
@@ -1070,10 +1719,15 @@ impl Game for WalkTheDog {
             self.machine.replace(machine.update(keystate));
         }
         assert!(self.machine.is_some());
+
+        self.ticks = self.ticks.wrapping_add(1);
+        if self.ticks.is_multiple_of(SNAPSHOT_INTERVAL) {
+            self.save_snapshot();
+        }
     }
 
     fn draw(&self, renderer: &Renderer) {
-        renderer.clear(&Rect::new_from_x_y(0, 0, 600, 600));
+        renderer.clear(&Rect::new_from_x_y(0.0, 0.0, 600.0, 600.0));
 
         if let Some(machine) = &self.machine {
             machine.draw(renderer);
@@ -1081,78 +1735,424 @@ impl Game for WalkTheDog {
     }
 }
 
-fn rightmost(obstacle_list: &[Box<dyn Obstacle>]) -> i16 {
+fn bind_mixer_controls(audio: &Audio, storage: Rc<dyn Storage>) -> Result<(), EngineError> {
+    let settings = audio.settings();
+
+    for (id, bus) in [
+        ("master_volume", Bus::Master),
+        ("music_volume", Bus::Music),
+        ("effects_volume", Bus::Effects),
+    ] {
+        let slider = browser::find_input_element_by_id(id)?;
+        slider.set_value_as_number(settings.volume(bus) as f64);
+        let audio = audio.clone();
+        let storage = storage.clone();
+        let input = slider.clone();
+        engine::add_input_handler(&slider, move || {
+            audio.set_volume(bus, input.value_as_number() as f32);
+            save_mixer_settings(&audio, storage.as_ref());
+        });
+    }
+
+    let mute = browser::find_input_element_by_id("mute")?;
+    mute.set_checked(settings.muted);
+    let audio = audio.clone();
+    let input = mute.clone();
+    engine::add_input_handler(&mute, move || {
+        audio.set_muted(input.checked());
+        save_mixer_settings(&audio, storage.as_ref());
+    });
+
+    Ok(())
+}
+
+fn save_mixer_settings(audio: &Audio, storage: &dyn Storage) {
+    if let Err(err) = persistence::save_mixer_settings(storage, &audio.settings()) {
+        error!("Could not save mixer settings {:#?}", err);
+    }
+}
+
+fn game_over_ui(score: u32, high_scores: &[HighScore], new_record: Option<usize>) -> String {
+    let rows: String = high_scores
+        .iter()
+        .enumerate()
+        .map(|(rank, high_score)| {
+            format!(
+                "<li{}>{} <span>{}</span></li>",
+                if Some(rank) == new_record {
+                    " class='new-record'"
+                } else {
+                    ""
+                },
+                high_score.score,
+                browser::escape_html(high_score.date.get(..10).unwrap_or(&high_score.date)),
+            )
+        })
+        .collect();
+    let title = if new_record == Some(0) {
+        "New Record!"
+    } else {
+        "Game Over"
+    };
+
+    format!(
+        "<div id='game_over'><h2>{}</h2><p>Score: {}</p><ol class='leaderboard'>{}</ol>\
+         <button id='new_game'>New Game</button></div>",
+        title, score, rows
+    )
+}
+
+// Removes every collectible touched and returns the points they were worth,
+// along with where the first one was.
+fn collect<T>(obstacles: &mut Vec<T>, contacts: &[Contact]) -> (u32, Option<f32>) {
+    let mut points = 0;
+    let mut pickup_x = None;
+    let mut collected = vec![];
+    for contact in contacts {
+        if let ObstacleKind::Collectible { points: worth } = contact.kind {
+            points += worth;
+            collected.push(contact.obstacle);
+            pickup_x.get_or_insert(contact.bounds.center().x);
+        }
+    }
+
+    // Removing from the back keeps the remaining indices valid.
+    collected.sort_unstable();
+    collected.dedup();
+    collected.into_iter().rev().for_each(|index| {
+        obstacles.remove(index);
+    });
+    (points, pickup_x)
+}
+
+fn broad_phase(obstacles: &[Box<dyn Obstacle>]) -> BroadPhase {
+    BroadPhase::new(obstacles.iter().map(|obstacle| obstacle.bounding_box()))
+}
+
+fn rightmost(obstacle_list: &[Box<dyn Obstacle>]) -> f32 {
     obstacle_list
         .iter()
         .map(|obstacle| obstacle.right())
-        .max_by(|x, y| x.cmp(y))
-        .unwrap_or(0)
+        .max_by(|x, y| x.total_cmp(y))
+        .unwrap_or(0.0)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use futures::channel::mpsc::unbounded;
     use std::collections::HashMap;
-    use web_sys::{AudioBuffer, AudioBufferOptions};
-
-    use wasm_bindgen_test::wasm_bindgen_test;
 
     wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
 
-    #[wasm_bindgen_test]
-    fn test_transition_from_game_over_to_new_game() {
-        let (_, receiver) = unbounded();
-        let image = HtmlImageElement::new().unwrap();
-        let audio = Audio::new().unwrap();
-        let options = AudioBufferOptions::new(1, 44100.0);
-        let sound = Sound {
-            buffer: AudioBuffer::new(&options).unwrap(),
+    #[test]
+    fn contact_reports_the_side_of_a_landing() {
+        let actor = Rect::new_from_x_y(10.0, 0.0, 40.0, 100.0);
+        let platform = Rect::new_from_x_y(0.0, 95.0, 100.0, 50.0);
+
+        let contact = Contact::new(3, ObstacleKind::Platform, &actor, platform);
+
+        assert_eq!(contact.obstacle, 3);
+        assert_eq!(contact.side, Side::Top);
+    }
+
+    #[test]
+    fn collecting_removes_the_collectibles_and_scores_their_points() {
+        let boy = Rect::new_from_x_y(100.0, 300.0, 40.0, 80.0);
+        let bone = |x| Rect::new_from_x_y(x, 320.0, 20.0, 20.0);
+        let mut obstacles = vec!["stone", "bone", "platform", "bone"];
+        let contacts = [
+            Contact::new(0, ObstacleKind::Barrier, &boy, bone(90.0)),
+            Contact::new(
+                1,
+                ObstacleKind::Collectible { points: 10 },
+                &boy,
+                bone(110.0),
+            ),
+            Contact::new(
+                3,
+                ObstacleKind::Collectible { points: 5 },
+                &boy,
+                bone(130.0),
+            ),
+        ];
+
+        assert_eq!(collect(&mut obstacles, &contacts), (15, Some(120.0)));
+        assert_eq!(obstacles, vec!["stone", "platform"]);
+
+        assert_eq!(collect(&mut obstacles, &contacts[..1]), (0, None));
+        assert_eq!(obstacles, vec!["stone", "platform"]);
+    }
+
+    #[test]
+    fn game_over_ui_escapes_stored_dates() {
+        let high_scores = [HighScore {
+            score: 120,
+            seed: 7,
+            date: "<img src=x onerror=alert(1)>".to_string(),
+        }];
+
+        let html = game_over_ui(120, &high_scores, Some(0));
+
+        assert!(html.contains("<li class='new-record'>120 <span>&lt;img src=x</span></li>"));
+        assert!(!html.contains("<img"));
+    }
+
+    #[test]
+    fn red_hat_boy_keeps_sub_pixel_velocity_between_ticks() {
+        let context = RedHatBoyContext {
+            elapsed: 0.0,
+            position: Point { x: 0.0, y: 100.0 },
+            velocity: Point { x: 0.0, y: 0.5 },
         };
-        let rhb = RedHatBoy::new(
-            Sheet {
-                frames: HashMap::new(),
-            },
-            image.clone(),
-            audio,
-            sound,
-        );
-        let sprite_sheet = SpriteSheet::new(
-            Sheet {
-                frames: HashMap::new(),
-            },
-            image.clone(),
+
+        let context = context.update();
+
+        assert_eq!(context.velocity.y, 1.5);
+        assert_eq!(context.position.y, 101.5);
+    }
+
+    #[test]
+    fn red_hat_boy_state_round_trips_through_a_snapshot() {
+        let machine: RedHatBoyStateMachine = RedHatBoyState::new().run().into();
+        let machine = machine
+            .transition(Event::Jump)
+            .update(&Animation::default());
+
+        let json = serde_json::to_string(&machine).unwrap();
+        let restored: RedHatBoyStateMachine = serde_json::from_str(&json).unwrap();
+
+        assert!(matches!(restored, RedHatBoyStateMachine::Jumping(_)));
+        assert_eq!(restored.context().position, machine.context().position);
+        assert_eq!(restored.context().velocity, machine.context().velocity);
+    }
+
+    #[test]
+    fn a_jump_landing_on_the_floor_plays_the_landing_sound_once() {
+        let running: RedHatBoyStateMachine = RedHatBoyState::new().run().into();
+        let mut machine = running.clone().transition(Event::Jump);
+        let mut landings = 0;
+
+        for _ in 0..100 {
+            let previous = machine.clone();
+            machine = machine.update(&Animation::default());
+            if machine.landed_from(&previous) {
+                landings += 1;
+            }
+        }
+
+        assert!(matches!(machine, RedHatBoyStateMachine::Running(_)));
+        assert_eq!(landings, 1);
+        assert!(!running
+            .clone()
+            .update(&Animation::default())
+            .landed_from(&running));
+    }
+
+    #[test]
+    fn the_backgrounds_scroll_at_different_rates() {
+        let layers: Vec<ParallaxLayerConfig> =
+            serde_json::from_str(include_str!("../static/backgrounds.json")).unwrap();
+
+        assert_eq!(layers[0].layer, Layer::Background);
+        assert!(layers.iter().any(|layer| layer.layer == Layer::Foreground));
+        assert!(layers
+            .iter()
+            .any(|layer| layer.scroll_factor != layers[0].scroll_factor));
+    }
+
+    #[test]
+    fn running_layers_a_beat_over_the_song_in_one_track() {
+        let tracks: Vec<MusicTrackConfig> =
+            serde_json::from_str(include_str!("../static/music.json")).unwrap();
+        let effects: HashMap<String, SynthParams> =
+            serde_json::from_str(include_str!("../static/sfx.json")).unwrap();
+
+        let theme = tracks
+            .iter()
+            .find(|track| track.name == MUSIC_TRACK)
+            .unwrap();
+        assert!(theme.stems.iter().any(|stem| stem.intensity == 0.0));
+        let pulse = theme
+            .stems
+            .iter()
+            .find(|stem| effects.contains_key(&stem.file))
+            .unwrap();
+        assert!(pulse.intensity > 0.0);
+        assert!(
+            (effects[&pulse.file].envelope.duration() as f64 - theme.tempo.beat_length()).abs()
+                < 1e-4
         );
+    }
 
-        let walk = Walk {
-            boy: rhb,
-            backgrounds: [
-                Image::new(image.clone(), Point { x: 0, y: 0 }),
-                Image::new(image.clone(), Point { x: 0, y: 0 }),
+    fn snapshot_with_every_obstacle() -> WalkSnapshot {
+        let machine: RedHatBoyStateMachine = RedHatBoyState::new().run().into();
+        WalkSnapshot {
+            boy: machine.transition(Event::Jump),
+            obstacles: vec![
+                ObstacleSnapshot::Barrier {
+                    position: Point { x: 150.0, y: 546.0 },
+                },
+                ObstacleSnapshot::Collectible {
+                    position: Point { x: 250.0, y: 300.0 },
+                    points: 100,
+                },
+                ObstacleSnapshot::Platform {
+                    sprites: vec!["13.png".to_string(), "14.png".to_string()],
+                    bounding_boxes: vec![Rect::new_from_x_y(0.0, 0.0, 128.0, 93.0)],
+                    position: Point { x: 370.0, y: 400.0 },
+                },
             ],
-            obstacles: vec![],
-            obstacle_sheet: Rc::new(sprite_sheet),
-            stone: image.clone(),
-            timeline: 0,
-        };
+            timeline: 498.0,
+            score: 1200,
+            seed: 7,
+            rng: ChaCha8Rng::seed_from_u64(7),
+        }
+    }
 
-        let document = browser::document().unwrap();
-        document
-            .body()
-            .unwrap()
-            .insert_adjacent_html("afterbegin", "<div id='ui'></div>")
-            .unwrap();
-        browser::draw_ui("<p>This is the UI</p>").unwrap();
+    #[test]
+    fn game_snapshot_round_trips_through_storage() {
+        let storage = MemoryStorage::default();
+        assert_eq!(
+            persistence::load_snapshot::<GameSnapshot>(&storage).unwrap(),
+            None
+        );
 
-        let state = WalkTheDogState {
-            _state: GameOver {
-                new_game_event: receiver,
-            },
-            walk: walk,
+        let snapshot = GameSnapshot::GameOver {
+            walk: snapshot_with_every_obstacle(),
+            new_record: Some(2),
         };
+        persistence::save_snapshot(&storage, &snapshot).unwrap();
+
+        assert_eq!(
+            persistence::load_snapshot::<GameSnapshot>(&storage).unwrap(),
+            Some(snapshot)
+        );
+    }
+
+    // A Walk holds images and an AudioContext, so only a browser can build one.
+    #[cfg(target_arch = "wasm32")]
+    mod in_browser {
+        use super::*;
+        use crate::engine::{Sheet, Variation};
+        use futures::channel::mpsc::unbounded;
+        use wasm_bindgen_test::wasm_bindgen_test;
+        use web_sys::{AudioBuffer, AudioBufferOptions};
+
+        fn test_walk() -> Walk {
+            let image = HtmlImageElement::new().unwrap();
+            let audio = Audio::new().unwrap();
+            let options = AudioBufferOptions::new(1, 44100.0);
+            let sound = Sound {
+                buffer: AudioBuffer::new(&options).unwrap(),
+                volume: 1.0,
+                variation: Variation::default(),
+            };
+            let music = Music::new(audio.clone());
+            let rhb = RedHatBoy::new(
+                SpriteSheet::new(
+                    Sheet {
+                        frames: HashMap::new(),
+                        animations: HashMap::new(),
+                    },
+                    image.clone(),
+                ),
+                audio.clone(),
+                RedHatBoySounds {
+                    jump: sound.clone(),
+                    land: sound.clone(),
+                    knock_out: sound.clone(),
+                },
+            );
+            let sprite_sheet = SpriteSheet::new(
+                Sheet {
+                    frames: HashMap::new(),
+                    animations: HashMap::new(),
+                },
+                image.clone(),
+            );
+
+            Walk {
+                boy: rhb,
+                backgrounds: vec![ParallaxLayer::new(
+                    image.clone(),
+                    1.0,
+                    0.0,
+                    Layer::Background,
+                )],
+                obstacles: vec![],
+                broad_phase: BroadPhase::default(),
+                obstacle_sheet: Rc::new(sprite_sheet),
+                stone: image.clone(),
+                bone: image.clone(),
+                audio,
+                music,
+                pickup_sound: sound,
+                timeline: 0.0,
+                camera: Camera::default(),
+                score: 0,
+                seed: 0,
+                rng: ChaCha8Rng::seed_from_u64(0),
+                leaderboard: Leaderboard::new(Rc::new(MemoryStorage::default())),
+            }
+        }
+
+        fn add_ui() {
+            browser::document()
+                .unwrap()
+                .body()
+                .unwrap()
+                .insert_adjacent_html("afterbegin", "<div id='ui'></div>")
+                .unwrap();
+        }
+
+        #[wasm_bindgen_test]
+        fn walk_restores_every_obstacle_from_a_snapshot() {
+            let walk = Walk::restore(test_walk(), snapshot_with_every_obstacle());
 
-        state.new_game();
-        let ui = browser::find_html_element_by_id("ui").unwrap();
-        assert_eq!(ui.child_element_count(), 0);
+            assert_eq!(walk.obstacles.len(), 3);
+            assert_eq!(walk.snapshot(), snapshot_with_every_obstacle());
+        }
+
+        #[wasm_bindgen_test]
+        fn restored_game_over_keeps_its_new_record() {
+            add_ui();
+            let snapshot = GameSnapshot::GameOver {
+                walk: snapshot_with_every_obstacle(),
+                new_record: Some(0),
+            };
+
+            let machine = WalkTheDogStateMachine::new(test_walk()).restore(snapshot);
+
+            assert_eq!(
+                machine.snapshot(),
+                GameSnapshot::GameOver {
+                    walk: snapshot_with_every_obstacle(),
+                    new_record: Some(0),
+                }
+            );
+            let ui = browser::find_html_element_by_id("ui").unwrap();
+            assert!(ui.inner_html().contains("new-record"));
+        }
+
+        #[wasm_bindgen_test]
+        fn test_transition_from_game_over_to_new_game() {
+            let (_, receiver) = unbounded();
+            let walk = test_walk();
+
+            add_ui();
+            browser::draw_ui("<p>This is the UI</p>").unwrap();
+
+            let state = WalkTheDogState {
+                _state: GameOver {
+                    new_game_event: receiver,
+                    new_record: None,
+                },
+                walk: walk,
+            };
+
+            state.new_game();
+            let ui = browser::find_html_element_by_id("ui").unwrap();
+            assert_eq!(ui.child_element_count(), 0);
+        }
     }
 }
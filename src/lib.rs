@@ -1,9 +1,13 @@
 #[macro_use]
 mod browser;
+mod assets;
 mod engine;
+mod error;
 mod game;
+mod persistence;
 mod segments;
 mod sound;
+mod synth;
 mod utils;
 
 use engine::GameLoop;
@@ -0,0 +1,236 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use anyhow::{anyhow, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    browser,
+    engine::{Bus, MixerSettings},
+};
+
+const HIGH_SCORES_KEY: &str = "walk-the-dog.high-scores";
+const MAX_HIGH_SCORES: usize = 10;
+const MIXER_SETTINGS_KEY: &str = "walk-the-dog.mixer";
+const SNAPSHOT_KEY: &str = "walk-the-dog.snapshot";
+
+pub trait Storage {
+    fn get_item(&self, key: &str) -> Result<Option<String>>;
+    fn set_item(&self, key: &str, value: &str) -> Result<()>;
+}
+
+pub struct LocalStorage {
+    storage: web_sys::Storage,
+}
+
+impl LocalStorage {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            storage: browser::local_storage().map_err(|err| anyhow!("{:#?}", err))?,
+        })
+    }
+}
+
+impl Storage for LocalStorage {
+    fn get_item(&self, key: &str) -> Result<Option<String>> {
+        self.storage
+            .get_item(key)
+            .map_err(|err| anyhow!("Could not read {} from local storage {:#?}", key, err))
+    }
+
+    fn set_item(&self, key: &str, value: &str) -> Result<()> {
+        self.storage
+            .set_item(key, value)
+            .map_err(|err| anyhow!("Could not write {} to local storage {:#?}", key, err))
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryStorage {
+    items: RefCell<HashMap<String, String>>,
+}
+
+impl Storage for MemoryStorage {
+    fn get_item(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.items.borrow().get(key).cloned())
+    }
+
+    fn set_item(&self, key: &str, value: &str) -> Result<()> {
+        self.items
+            .borrow_mut()
+            .insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HighScore {
+    pub score: u32,
+    pub seed: u64,
+    pub date: String,
+}
+
+pub struct Leaderboard {
+    storage: Rc<dyn Storage>,
+    scores: Vec<HighScore>,
+}
+
+impl Leaderboard {
+    pub fn new(storage: Rc<dyn Storage>) -> Self {
+        Self {
+            storage,
+            scores: vec![],
+        }
+    }
+
+    pub fn load(storage: Rc<dyn Storage>) -> Result<Self> {
+        let scores = match storage.get_item(HIGH_SCORES_KEY)? {
+            Some(json) => serde_json::from_str(&json)
+                .map_err(|err| anyhow!("Could not parse high scores {:#?}", err))?,
+            None => vec![],
+        };
+
+        Ok(Self { storage, scores })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let json = serde_json::to_string(&self.scores)
+            .map_err(|err| anyhow!("Could not serialize high scores {:#?}", err))?;
+        self.storage.set_item(HIGH_SCORES_KEY, &json)
+    }
+
+    pub fn scores(&self) -> &[HighScore] {
+        &self.scores
+    }
+
+    // Returns the rank of the new entry, or None if it did not make the board.
+    pub fn submit(&mut self, entry: HighScore) -> Option<usize> {
+        let rank = self
+            .scores
+            .iter()
+            .position(|high_score| entry.score > high_score.score)
+            .unwrap_or(self.scores.len());
+
+        if rank >= MAX_HIGH_SCORES {
+            return None;
+        }
+
+        self.scores.insert(rank, entry);
+        self.scores.truncate(MAX_HIGH_SCORES);
+        Some(rank)
+    }
+}
+
+// Stored levels are only as trustworthy as whoever last edited them, so
+// each goes back through set_volume to be clamped.
+pub fn load_mixer_settings(storage: &dyn Storage) -> Result<MixerSettings> {
+    match storage.get_item(MIXER_SETTINGS_KEY)? {
+        Some(json) => serde_json::from_str(&json)
+            .map(|mut settings: MixerSettings| {
+                for bus in [Bus::Master, Bus::Music, Bus::Effects] {
+                    settings.set_volume(bus, settings.volume(bus));
+                }
+                settings
+            })
+            .map_err(|err| anyhow!("Could not parse mixer settings {:#?}", err)),
+        None => Ok(MixerSettings::default()),
+    }
+}
+
+pub fn save_mixer_settings(storage: &dyn Storage, settings: &MixerSettings) -> Result<()> {
+    let json = serde_json::to_string(settings)
+        .map_err(|err| anyhow!("Could not serialize mixer settings {:#?}", err))?;
+    storage.set_item(MIXER_SETTINGS_KEY, &json)
+}
+
+pub fn load_snapshot<T: DeserializeOwned>(storage: &dyn Storage) -> Result<Option<T>> {
+    storage
+        .get_item(SNAPSHOT_KEY)?
+        .map(|json| {
+            serde_json::from_str(&json)
+                .map_err(|err| anyhow!("Could not parse snapshot {:#?}", err))
+        })
+        .transpose()
+}
+
+pub fn save_snapshot<T: Serialize>(storage: &dyn Storage, snapshot: &T) -> Result<()> {
+    let json = serde_json::to_string(snapshot)
+        .map_err(|err| anyhow!("Could not serialize snapshot {:#?}", err))?;
+    storage.set_item(SNAPSHOT_KEY, &json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn high_score(score: u32) -> HighScore {
+        HighScore {
+            score,
+            seed: 42,
+            date: "2023-09-01T00:00:00.000Z".to_string(),
+        }
+    }
+
+    #[test]
+    fn submitted_scores_are_ranked_and_capped() {
+        let mut leaderboard = Leaderboard::new(Rc::new(MemoryStorage::default()));
+        (1..=MAX_HIGH_SCORES as u32).for_each(|score| {
+            leaderboard.submit(high_score(score * 10));
+        });
+
+        assert_eq!(leaderboard.submit(high_score(5)), None);
+        assert_eq!(leaderboard.submit(high_score(55)), Some(5));
+        assert_eq!(leaderboard.scores().len(), MAX_HIGH_SCORES);
+        assert_eq!(leaderboard.scores()[0].score, 100);
+        assert_eq!(leaderboard.scores().last().unwrap().score, 20);
+    }
+
+    #[test]
+    fn leaderboard_round_trips_through_storage() {
+        let storage: Rc<dyn Storage> = Rc::new(MemoryStorage::default());
+        let mut leaderboard = Leaderboard::load(storage.clone()).unwrap();
+        assert_eq!(leaderboard.submit(high_score(300)), Some(0));
+        leaderboard.save().unwrap();
+
+        let reloaded = Leaderboard::load(storage).unwrap();
+        assert_eq!(reloaded.scores(), &[high_score(300)]);
+    }
+
+    #[test]
+    fn mixer_settings_round_trip_through_storage() {
+        let storage = MemoryStorage::default();
+        assert_eq!(
+            load_mixer_settings(&storage).unwrap(),
+            MixerSettings::default()
+        );
+
+        let settings = MixerSettings {
+            music: 0.25,
+            muted: true,
+            ..MixerSettings::default()
+        };
+        save_mixer_settings(&storage, &settings).unwrap();
+
+        assert_eq!(load_mixer_settings(&storage).unwrap(), settings);
+    }
+
+    #[test]
+    fn stored_mixer_levels_are_clamped_on_load() {
+        let storage = MemoryStorage::default();
+        storage
+            .set_item(
+                MIXER_SETTINGS_KEY,
+                r#"{"master": 4.0, "music": -1.0, "effects": 0.5, "muted": false}"#,
+            )
+            .unwrap();
+
+        assert_eq!(
+            load_mixer_settings(&storage).unwrap(),
+            MixerSettings {
+                master: 1.0,
+                music: 0.0,
+                effects: 0.5,
+                muted: false,
+            }
+        );
+    }
+}
@@ -4,26 +4,31 @@ use web_sys::HtmlImageElement;
 
 use crate::{
     engine::{Image, Point, Rect, SpriteSheet},
-    game::{Barrier, Obstacle, Platform},
+    game::{Barrier, Collectible, Obstacle, Platform},
 };
 
-const LOW_PLATFORM: i16 = 420;
-const HIGH_PLATFORM: i16 = 375;
-const FIRST_PLATFORM: i16 = 370;
-const STONE_ON_GROUND: i16 = 546;
+const LOW_PLATFORM: f32 = 420.0;
+const HIGH_PLATFORM: f32 = 375.0;
+const FIRST_PLATFORM: f32 = 370.0;
+const STONE_ON_GROUND: f32 = 546.0;
+const BONE_OVER_STONE: f32 = 440.0;
+const BONE_ON_PLATFORM: f32 = 330.0;
+const BONE_POINTS: u32 = 100;
 const FLOATING_PLATFORM_SPRITES: [&str; 3] = ["13.png", "14.png", "15.png"];
 const FLOATING_PLATFORM_BOUNDING_BOXES: [Rect; 3] = [
-    Rect::new_from_x_y(0, 0, 60, 54),
-    Rect::new_from_x_y(60, 0, 384 - (60 * 2), 93),
-    Rect::new_from_x_y(384 - 60, 0, 60, 54),
+    Rect::new_from_x_y(0.0, 0.0, 60.0, 54.0),
+    Rect::new_from_x_y(60.0, 0.0, 384.0 - (60.0 * 2.0), 93.0),
+    Rect::new_from_x_y(384.0 - 60.0, 0.0, 60.0, 54.0),
 ];
 
 pub fn stone_and_platform(
     stone: HtmlImageElement,
+    bone: HtmlImageElement,
     sprite_sheet: Rc<SpriteSheet>,
-    offset_x: i16,
+    offset_x: f32,
 ) -> Vec<Box<dyn Obstacle>> {
-    const ININITIAL_STONE_OFFSET: i16 = 150;
+    const ININITIAL_STONE_OFFSET: f32 = 150.0;
+    const INITIAL_BONE_OFFSET: f32 = 450.0;
     vec![
         Box::new(Barrier::new(Image::new(
             stone,
@@ -39,16 +44,25 @@ pub fn stone_and_platform(
                 y: LOW_PLATFORM,
             },
         )),
+        Box::new(create_bone(
+            bone,
+            Point {
+                x: offset_x + INITIAL_BONE_OFFSET,
+                y: BONE_ON_PLATFORM,
+            },
+        )),
     ]
 }
 
 pub fn platform_and_stone(
     stone: HtmlImageElement,
+    bone: HtmlImageElement,
     sprite_sheet: Rc<SpriteSheet>,
-    offset_x: i16,
+    offset_x: f32,
 ) -> Vec<Box<dyn Obstacle>> {
-    const INITIAL_STONE_OFFSET: i16 = 400;
-    const INITIAL_PLATFORM_OFFSET: i16 = 200;
+    const INITIAL_STONE_OFFSET: f32 = 400.0;
+    const INITIAL_PLATFORM_OFFSET: f32 = 200.0;
+    const INITIAL_BONE_OFFSET: f32 = 410.0;
 
     vec![
         Box::new(Barrier::new(Image::new(
@@ -58,6 +72,13 @@ pub fn platform_and_stone(
                 y: STONE_ON_GROUND,
             },
         ))),
+        Box::new(create_bone(
+            bone,
+            Point {
+                x: offset_x + INITIAL_BONE_OFFSET,
+                y: BONE_OVER_STONE,
+            },
+        )),
         Box::new(create_floating_platform(
             sprite_sheet,
             Point {
@@ -68,6 +89,10 @@ pub fn platform_and_stone(
     ]
 }
 
+fn create_bone(bone: HtmlImageElement, position: Point) -> Collectible {
+    Collectible::new(Image::new(bone, position), BONE_POINTS)
+}
+
 fn create_floating_platform(sprite_sheet: Rc<SpriteSheet>, position: Point) -> Platform {
     Platform::new(
         sprite_sheet,
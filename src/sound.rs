@@ -1,60 +1,141 @@
-use anyhow::{anyhow, Result};
 use js_sys::ArrayBuffer;
 use wasm_bindgen::JsCast;
 use wasm_bindgen_futures::JsFuture;
-use web_sys::{AudioBuffer, AudioBufferSourceNode, AudioContext, AudioDestinationNode, AudioNode};
+use web_sys::{
+    AudioBuffer, AudioBufferSourceNode, AudioContext, AudioNode, AudioParam,
+    AudioScheduledSourceNode, GainNode, StereoPannerNode,
+};
+
+use crate::error::{EngineError, Result};
 
 pub fn create_audio_context() -> Result<AudioContext> {
-    AudioContext::new().map_err(|err| anyhow!("Could not create audio context: {:#?}", err))
+    AudioContext::new().map_err(|err| EngineError::audio("Could not create audio context", err))
 }
 
 fn create_buffer_source(ctx: &AudioContext) -> Result<AudioBufferSourceNode> {
     ctx.create_buffer_source()
-        .map_err(|err| anyhow!("Error creating buffer source: {:#?}", err))
+        .map_err(|err| EngineError::audio("Error creating buffer source", err))
+}
+
+pub fn create_gain(ctx: &AudioContext, value: f32) -> Result<GainNode> {
+    let gain = ctx
+        .create_gain()
+        .map_err(|err| EngineError::audio("Error creating gain node", err))?;
+    gain.gain().set_value(value);
+    Ok(gain)
+}
+
+pub fn create_stereo_panner(ctx: &AudioContext, pan: f32) -> Result<StereoPannerNode> {
+    let panner = ctx
+        .create_stereo_panner()
+        .map_err(|err| EngineError::audio("Error creating stereo panner", err))?;
+    panner.pan().set_value(pan);
+    Ok(panner)
 }
 
-fn connect_with_audio_node(
-    buffer_source: &AudioBufferSourceNode,
-    destination: &AudioDestinationNode,
-) -> Result<AudioNode> {
-    buffer_source
+pub fn connect_with_audio_node(source: &AudioNode, destination: &AudioNode) -> Result<AudioNode> {
+    source
         .connect_with_audio_node(destination)
-        .map_err(|err| anyhow!("Error connecting audio source to destination {:#?}", err))
+        .map_err(|err| EngineError::audio("Error connecting audio source to destination", err))
 }
 
-fn create_track_source(ctx: &AudioContext, buffer: &AudioBuffer) -> Result<AudioBufferSourceNode> {
+pub fn create_track_source(
+    ctx: &AudioContext,
+    buffer: &AudioBuffer,
+    looping: Looping,
+    destination: &AudioNode,
+) -> Result<AudioBufferSourceNode> {
     let track_source = create_buffer_source(ctx)?;
     track_source.set_buffer(Some(buffer));
-    connect_with_audio_node(&track_source, &ctx.destination())?;
+    if matches!(looping, Looping::Yes) {
+        track_source.set_loop(true);
+    }
+    connect_with_audio_node(&track_source, destination)?;
     Ok(track_source)
 }
 
+#[derive(Clone, Copy)]
 pub enum Looping {
     No,
     Yes,
 }
 
-pub fn play_sound(ctx: &AudioContext, buffer: &AudioBuffer, looping: Looping) -> Result<()> {
-    let track_source = create_track_source(ctx, buffer)?;
-    if matches!(looping, Looping::Yes) {
-        track_source.set_loop(true);
-    }
-
+pub fn start_sound(track_source: &AudioBufferSourceNode, when: f64, offset: f64) -> Result<()> {
     track_source
-        .start()
-        .map_err(|err| anyhow!("Could not start sound!{:#?}", err))
+        .start_with_when_and_grain_offset(when, offset)
+        .map_err(|err| EngineError::audio("Could not start sound", err))
+}
+
+pub fn stop_sound(track_source: &AudioBufferSourceNode, when: f64) -> Result<()> {
+    AudioScheduledSourceNode::stop_with_when(track_source, when)
+        .map_err(|err| EngineError::audio("Could not stop sound", err))
+}
+
+// Ramps from wherever the parameter currently is, dropping any earlier ramp.
+pub fn ramp_to_value(
+    ctx: &AudioContext,
+    param: &AudioParam,
+    value: f32,
+    seconds: f64,
+) -> Result<()> {
+    let now = ctx.current_time();
+    param
+        .cancel_scheduled_values(now)
+        .and_then(|param| param.set_value_at_time(param.value(), now))
+        .and_then(|param| param.linear_ramp_to_value_at_time(value, now + seconds.max(0.0)))
+        .map(|_param| ())
+        .map_err(|err| EngineError::audio("Could not ramp audio parameter", err))
+}
+
+// Eases towards the value from `when` without needing to know where the
+// parameter will be by then. A time constant of a quarter of the duration
+// gets within 2% of the target.
+pub fn glide_to_value(param: &AudioParam, value: f32, when: f64, seconds: f64) -> Result<()> {
+    param
+        .cancel_scheduled_values(when)
+        .and_then(|param| param.set_target_at_time(value, when, seconds.max(0.01) / 4.0))
+        .map(|_param| ())
+        .map_err(|err| EngineError::audio("Could not glide audio parameter", err))
+}
+
+pub fn set_value_at_time(param: &AudioParam, value: f32, when: f64) -> Result<()> {
+    param
+        .set_value_at_time(value, when)
+        .map(|_param| ())
+        .map_err(|err| EngineError::audio("Could not schedule audio parameter", err))
+}
+
+pub fn create_buffer(ctx: &AudioContext, samples: &[f32], sample_rate: f32) -> Result<AudioBuffer> {
+    let buffer = ctx
+        .create_buffer(1, samples.len().max(1) as u32, sample_rate)
+        .map_err(|err| EngineError::audio("Could not create audio buffer", err))?;
+    buffer
+        .copy_to_channel(samples, 0)
+        .map_err(|err| EngineError::audio("Could not fill audio buffer", err))?;
+    Ok(buffer)
+}
+
+pub async fn resume_context(ctx: &AudioContext) -> Result<()> {
+    JsFuture::from(
+        ctx.resume()
+            .map_err(|err| EngineError::audio("Could not resume audio context", err))?,
+    )
+    .await
+    .map(|_value| ())
+    .map_err(|err| EngineError::audio("Audio context refused to resume", err))
 }
 
 pub async fn decode_audio_data(
     ctx: &AudioContext,
+    resource: &str,
     array_beffer: &ArrayBuffer,
 ) -> Result<AudioBuffer> {
     JsFuture::from(
         ctx.decode_audio_data(array_beffer)
-            .map_err(|err| anyhow!("Could not decode audio from array buffer {:#?}", err))?,
+            .map_err(|err| EngineError::audio_decode(resource, err))?,
     )
     .await
-    .map_err(|err| anyhow!("Could not convert promise to future {:#?}", err))?
+    .map_err(|err| EngineError::audio_decode(resource, err))?
     .dyn_into()
-    .map_err(|err| anyhow!("Could not cast into AudioBuffer {:#?}", err))
+    .map_err(|err| EngineError::audio_decode(resource, err))
 }
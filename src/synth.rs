@@ -0,0 +1,287 @@
+use std::f32::consts::TAU;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Waveform {
+    Square,
+    Sawtooth,
+    Sine,
+    Triangle,
+    Noise,
+}
+
+// Times are in seconds. Punch boosts the start of the sustain by that
+// fraction, which is what gives pickups their snap.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Envelope {
+    pub attack: f32,
+    pub sustain: f32,
+    pub punch: f32,
+    pub decay: f32,
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Envelope {
+            attack: 0.0,
+            sustain: 0.1,
+            punch: 0.0,
+            decay: 0.2,
+        }
+    }
+}
+
+impl Envelope {
+    pub fn duration(&self) -> f32 {
+        self.attack.max(0.0) + self.sustain.max(0.0) + self.decay.max(0.0)
+    }
+
+    fn level(&self, time: f32) -> f32 {
+        if time < self.attack {
+            return time / self.attack;
+        }
+        let time = time - self.attack.max(0.0);
+        if time < self.sustain {
+            return 1.0 + self.punch * (1.0 - time / self.sustain);
+        }
+        let time = time - self.sustain.max(0.0);
+        if time < self.decay {
+            1.0 - time / self.decay
+        } else {
+            0.0
+        }
+    }
+}
+
+// An sfxr-style description of a sound. Every field has a default, a
+// square wave included, so a description only lists what it changes.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SynthParams {
+    pub waveform: Waveform,
+    pub frequency: f32,
+    // Octaves per second, negative slides down.
+    pub frequency_slide: f32,
+    pub vibrato_depth: f32,
+    pub vibrato_speed: f32,
+    // Fraction of each square wave period spent high.
+    pub duty: f32,
+    pub envelope: Envelope,
+    pub low_pass: Option<f32>,
+    pub high_pass: Option<f32>,
+    pub volume: f32,
+    pub seed: u64,
+}
+
+impl Default for SynthParams {
+    fn default() -> Self {
+        SynthParams {
+            waveform: Waveform::Square,
+            frequency: 440.0,
+            frequency_slide: 0.0,
+            vibrato_depth: 0.0,
+            vibrato_speed: 0.0,
+            duty: 0.5,
+            envelope: Envelope::default(),
+            low_pass: None,
+            high_pass: None,
+            volume: 0.5,
+            seed: 0,
+        }
+    }
+}
+
+const MIN_FREQUENCY: f32 = 20.0;
+
+pub fn synthesize(params: &SynthParams, sample_rate: f32) -> Vec<f32> {
+    let length = (params.envelope.duration() * sample_rate).ceil() as usize;
+    let mut rng = ChaCha8Rng::seed_from_u64(params.seed);
+    let mut noise = rng.gen_range(-1.0..=1.0);
+    let mut phase = 0.0;
+    let mut low_pass = params
+        .low_pass
+        .map(|cutoff| OnePole::low_pass(cutoff, sample_rate));
+    let mut high_pass = params
+        .high_pass
+        .map(|cutoff| OnePole::high_pass(cutoff, sample_rate));
+
+    (0..length)
+        .map(|index| {
+            let time = index as f32 / sample_rate;
+            let sample = match params.waveform {
+                Waveform::Square => {
+                    if phase < params.duty.clamp(0.0, 1.0) {
+                        1.0
+                    } else {
+                        -1.0
+                    }
+                }
+                Waveform::Sawtooth => 2.0 * phase - 1.0,
+                Waveform::Sine => (TAU * phase).sin(),
+                Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+                Waveform::Noise => noise,
+            };
+
+            let vibrato = 1.0 + params.vibrato_depth * (TAU * params.vibrato_speed * time).sin();
+            let frequency = (params.frequency * (params.frequency_slide * time).exp2() * vibrato)
+                .max(MIN_FREQUENCY);
+            phase += frequency / sample_rate;
+            if phase >= 1.0 {
+                phase = phase.fract();
+                // Noise holds one value per period so its frequency still reads as pitch.
+                noise = rng.gen_range(-1.0..=1.0);
+            }
+
+            let sample = low_pass
+                .as_mut()
+                .map_or(sample, |filter| filter.apply(sample));
+            let sample = high_pass
+                .as_mut()
+                .map_or(sample, |filter| filter.apply(sample));
+            (sample * params.envelope.level(time) * params.volume).clamp(-1.0, 1.0)
+        })
+        .collect()
+}
+
+enum OnePole {
+    LowPass { alpha: f32, output: f32 },
+    HighPass { alpha: f32, output: f32, input: f32 },
+}
+
+impl OnePole {
+    fn low_pass(cutoff: f32, sample_rate: f32) -> Self {
+        OnePole::LowPass {
+            alpha: 1.0 - (-TAU * cutoff / sample_rate).exp(),
+            output: 0.0,
+        }
+    }
+
+    fn high_pass(cutoff: f32, sample_rate: f32) -> Self {
+        let rc = 1.0 / (TAU * cutoff);
+        OnePole::HighPass {
+            alpha: rc / (rc + 1.0 / sample_rate),
+            output: 0.0,
+            input: 0.0,
+        }
+    }
+
+    fn apply(&mut self, sample: f32) -> f32 {
+        match self {
+            OnePole::LowPass { alpha, output } => {
+                *output += *alpha * (sample - *output);
+                *output
+            }
+            OnePole::HighPass {
+                alpha,
+                output,
+                input,
+            } => {
+                *output = *alpha * (*output + sample - *input);
+                *input = sample;
+                *output
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 8000.0;
+
+    fn energy(samples: &[f32]) -> f32 {
+        samples.iter().map(|sample| sample * sample).sum()
+    }
+
+    #[test]
+    fn sound_lasts_as_long_as_its_envelope() {
+        let params = SynthParams {
+            envelope: Envelope {
+                attack: 0.1,
+                sustain: 0.2,
+                punch: 0.5,
+                decay: 0.2,
+            },
+            ..SynthParams::default()
+        };
+
+        let samples = synthesize(&params, SAMPLE_RATE);
+
+        assert_eq!(samples.len(), 4000);
+        assert_eq!(samples[0], 0.0);
+        assert!(samples.iter().all(|sample| (-1.0..=1.0).contains(sample)));
+    }
+
+    #[test]
+    fn sine_rises_through_zero_once_per_period() {
+        let params = SynthParams {
+            waveform: Waveform::Sine,
+            frequency: 100.0,
+            envelope: Envelope {
+                attack: 0.0,
+                sustain: 1.0,
+                punch: 0.0,
+                decay: 0.0,
+            },
+            ..SynthParams::default()
+        };
+
+        let samples = synthesize(&params, SAMPLE_RATE);
+        let crossings = samples
+            .windows(2)
+            .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+            .count();
+
+        assert!((99..=100).contains(&crossings));
+    }
+
+    #[test]
+    fn noise_is_repeatable_for_a_seed() {
+        let params = SynthParams {
+            waveform: Waveform::Noise,
+            seed: 9,
+            ..SynthParams::default()
+        };
+
+        assert_eq!(
+            synthesize(&params, SAMPLE_RATE),
+            synthesize(&params, SAMPLE_RATE)
+        );
+        assert_ne!(
+            synthesize(&params, SAMPLE_RATE),
+            synthesize(&SynthParams { seed: 10, ..params }, SAMPLE_RATE)
+        );
+    }
+
+    #[test]
+    fn low_pass_takes_the_edge_off_high_notes() {
+        let params = SynthParams {
+            frequency: 2000.0,
+            ..SynthParams::default()
+        };
+        let filtered = SynthParams {
+            low_pass: Some(200.0),
+            ..params
+        };
+
+        assert!(
+            energy(&synthesize(&filtered, SAMPLE_RATE))
+                < energy(&synthesize(&params, SAMPLE_RATE)) / 4.0
+        );
+    }
+
+    #[test]
+    fn params_only_need_what_they_change() {
+        let params: SynthParams =
+            serde_json::from_str(r#"{"waveform": "Noise", "frequency_slide": -2.0}"#).unwrap();
+
+        assert_eq!(params.waveform, Waveform::Noise);
+        assert_eq!(params.frequency_slide, -2.0);
+        assert_eq!(params.envelope, Envelope::default());
+    }
+}